@@ -1,18 +1,19 @@
 // Copyright 2021 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
-use freedesktop_desktop_entry::{default_paths, get_languages_from_env, Iter, PathSource};
+use freedesktop_desktop_entry::{get_languages_from_env, DirLayout, Iter, Provenance};
 
 fn main() {
     let locales = get_languages_from_env();
+    let layout = DirLayout::from_env_lossy();
 
-    let entries = Iter::new(default_paths())
+    let entries = Iter::new(layout.application_dirs().into_iter())
         .entries(Some(&locales))
         .collect::<Vec<_>>();
 
     for entry in entries {
-        let path_src = PathSource::guess_from(&entry.path);
+        let provenance = Provenance::of_entry(&layout, &entry);
 
-        println!("{:?}: {}\n---\n{}", path_src, entry.path.display(), entry);
+        println!("{:?}: {}\n---\n{}", provenance, entry.path.display(), entry);
     }
 }
@@ -3,16 +3,35 @@
 
 use std::{fs, time::Duration};
 
-use freedesktop_desktop_entry::{DesktopEntry, Iter, default_paths, get_languages_from_env};
+use freedesktop_desktop_entry::{default_paths, get_languages_from_env, DesktopEntry, Iter};
 
 use std::time::Instant;
 
 fn main() {
     let it = 500;
 
+    bench_walk(it);
     bench(it);
 }
 
+fn bench_walk(it: u32) {
+    let mut total_time = Duration::ZERO;
+    let mut files = 0;
+
+    for _ in 0..it {
+        let now = Instant::now();
+
+        files = Iter::new(default_paths()).count();
+
+        total_time += now.elapsed();
+    }
+
+    println!(
+        "time to find all {files} .desktop files: {:.2?}",
+        total_time / it
+    );
+}
+
 fn bench(it: u32) {
     let mut total_time = Duration::ZERO;
 
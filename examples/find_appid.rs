@@ -3,15 +3,30 @@ use freedesktop_desktop_entry as fde;
 fn main() {
     let locales = fde::get_languages_from_env();
     let desktop_entries = fde::desktop_entries(&locales);
+    let matcher = fde::AppMatcher::new();
 
     for arg in std::env::args().skip(1) {
-        let arg = fde::unicase::Ascii::new(arg.as_str());
+        let app_id = fde::unicase::Ascii::new(arg.as_str());
 
-        let desktop_entry =
-            fde::find_app_by_id(&desktop_entries, arg).expect("could not find appid");
+        match fde::find_app_by_id(&desktop_entries, app_id) {
+            Some(desktop_entry) => {
+                let icon_source =
+                    fde::IconSource::from_unknown(desktop_entry.icon().unwrap_or_default());
 
-        let icon_source = fde::IconSource::from_unknown(desktop_entry.icon().unwrap_or_default());
+                println!("{arg}: {desktop_entry:#?} with icon {icon_source:?}");
+            }
+            None => println!("{arg}: could not find appid"),
+        }
 
-        println!("{arg}: {desktop_entry:#?} with icon {icon_source:?}");
+        // Every candidate, as ranked by the matcher.
+        for found in matcher.find_all(&desktop_entries, &arg) {
+            println!(
+                "  {} ({}): {} [score {}]",
+                found.entry.id(),
+                found.entry.path.display(),
+                found.reason,
+                found.score
+            );
+        }
     }
 }
@@ -0,0 +1,118 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Async counterparts of the blocking APIs. File system access runs on the bounded thread
+//! pool of the `blocking` crate, so they work with any async runtime.
+
+use std::path::PathBuf;
+
+use blocking::{unblock, Unblock};
+use futures_core::Stream;
+
+use crate::{DecodeError, DesktopEntry, Iter};
+
+/// Number of entries decoded ahead of the consumer of [`Iter::into_stream`].
+const STREAM_CAPACITY: usize = 64;
+
+impl DesktopEntry {
+    /// Like [`DesktopEntry::from_path`], without blocking the executor.
+    pub async fn from_path_async<L>(
+        path: impl Into<PathBuf>,
+        locales_filter: Option<&[L]>,
+    ) -> Result<DesktopEntry, DecodeError>
+    where
+        L: AsRef<str>,
+    {
+        let path = path.into();
+        let locales_filter = owned_locales(locales_filter);
+
+        unblock(move || DesktopEntry::from_path(path, locales_filter.as_deref())).await
+    }
+}
+
+impl Iter {
+    /// Like [`Iter::entries`], but as a [`Stream`] which walks directories and decodes entries
+    /// without blocking the executor.
+    pub fn into_stream<L>(
+        self,
+        locales_filter: Option<&[L]>,
+    ) -> impl Stream<Item = DesktopEntry> + Send + Unpin + 'static
+    where
+        L: AsRef<str>,
+    {
+        let locales_filter = owned_locales(locales_filter);
+
+        let fs = self.filesystem().clone();
+        let entries = self.with_ids().filter_map(move |(id, path)| {
+            let mut entry =
+                DesktopEntry::from_path_with(&*fs, path, locales_filter.as_deref()).ok()?;
+            entry.appid = id.into();
+            Some(entry)
+        });
+
+        Unblock::with_capacity(STREAM_CAPACITY, entries)
+    }
+}
+
+#[inline]
+fn owned_locales<L: AsRef<str>>(locales: Option<&[L]>) -> Option<Vec<String>> {
+    locales.map(|locales| locales.iter().map(|l| l.as_ref().to_owned()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, path::PathBuf};
+
+    use futures_lite::{future::block_on, StreamExt};
+
+    use crate::{DesktopEntry, Iter, LocaleMap};
+
+    #[allow(clippy::type_complexity)]
+    fn groups(entry: &DesktopEntry) -> Vec<(&str, &BTreeMap<String, (String, LocaleMap)>)> {
+        entry
+            .groups
+            .0
+            .iter()
+            .map(|(name, group)| (name.as_str(), &group.0))
+            .collect()
+    }
+
+    #[test]
+    fn from_path_async_matches_from_path() {
+        let path = PathBuf::from("tests_entries/org.mozilla.firefox.desktop");
+        let locales = &["fr_FR"];
+
+        let entry = block_on(DesktopEntry::from_path_async(&path, Some(locales))).unwrap();
+        let expected = DesktopEntry::from_path(&path, Some(locales)).unwrap();
+
+        assert_eq!(entry.path, expected.path);
+        assert_eq!(entry.appid, expected.appid);
+        assert_eq!(groups(&entry), groups(&expected));
+        assert_eq!(entry.generic_name(locales).unwrap(), "Navigateur Web");
+    }
+
+    #[test]
+    fn stream_yields_same_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        for name in ["c", "a", "b"] {
+            fs::write(
+                root.join(name).with_extension("desktop"),
+                DesktopEntry::from_appid(name.to_string()).to_string(),
+            )
+            .unwrap();
+        }
+
+        let expected = Iter::new([root.to_path_buf()].into_iter())
+            .entries(None::<&[&str]>)
+            .collect::<Vec<_>>();
+        let streamed = block_on(
+            Iter::new([root.to_path_buf()].into_iter())
+                .into_stream(None::<&[&str]>)
+                .collect::<Vec<_>>(),
+        );
+
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, expected);
+    }
+}
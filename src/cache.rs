@@ -0,0 +1,648 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Persistent cache of decoded desktop entries, validated by file size and modification time.
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{DesktopEntry, DirLayout, FileSystem, Group, Groups, Iter, LocaleMap, RealFs};
+
+const MAGIC: &[u8; 8] = b"FDECACHE";
+const VERSION: u32 = 2;
+
+/// Loads desktop entries through a cache file, only decoding files which changed since the
+/// cache was written.
+///
+/// The cache is replaced atomically, so concurrent writers never corrupt it. A cache which
+/// cannot be read for any reason is ignored, and every entry is decoded again.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    file: PathBuf,
+    source: Arc<dyn FileSystem>,
+}
+
+/// Result of [`Cache::load_entries`].
+#[derive(Debug, Default)]
+pub struct CachedEntries {
+    pub entries: Vec<DesktopEntry>,
+    /// Number of entries taken from the cache.
+    pub reused: usize,
+    /// Number of entries which had to be decoded.
+    pub decoded: usize,
+}
+
+impl Cache {
+    /// Use the given cache file.
+    #[inline]
+    pub fn new(file: impl Into<PathBuf>) -> Self {
+        Self {
+            file: file.into(),
+            source: Arc::new(RealFs),
+        }
+    }
+
+    /// The file system which entries are loaded from. Defaults to [`RealFs`]. The cache file
+    /// itself is always on the real file system.
+    #[inline]
+    pub fn filesystem(mut self, fs: Arc<dyn FileSystem>) -> Self {
+        self.source = fs;
+        self
+    }
+
+    /// Use `$XDG_CACHE_HOME/freedesktop-desktop-entry/entries.cache`.
+    ///
+    /// Returns `None` if the cache home cannot be determined.
+    #[inline]
+    pub fn in_cache_home() -> Option<Self> {
+        Self::in_cache_home_of(&DirLayout::from_env_lossy())
+    }
+
+    /// Use `freedesktop-desktop-entry/entries.cache` in the cache home of `layout`.
+    pub fn in_cache_home_of(layout: &DirLayout) -> Option<Self> {
+        let cache_home = layout.get_cache_home()?;
+        Some(Self::new(
+            cache_home.join("freedesktop-desktop-entry/entries.cache"),
+        ))
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.file
+    }
+
+    /// Load every desktop entry in `directories`, as [`Iter::entries`] would, and update the
+    /// cache file if anything changed.
+    ///
+    /// Files which fail to decode are remembered as well, and are not decoded again until
+    /// their size or modification time changes.
+    pub fn load_entries<I, L>(&self, directories: I, locales: Option<&[L]>) -> CachedEntries
+    where
+        I: IntoIterator<Item = PathBuf>,
+        L: AsRef<str>,
+    {
+        let roots = directories.into_iter().collect::<Vec<_>>();
+        let locales = locales.map(|locales| {
+            locales
+                .iter()
+                .map(|l| l.as_ref().to_owned())
+                .collect::<Vec<_>>()
+        });
+
+        let cached = match fs::read(&self.file) {
+            Ok(bytes) => match CacheData::decode(&bytes) {
+                Some(data) if data.locales == locales => Some(data),
+                Some(_) => None,
+                None => {
+                    log::warn!("ignoring corrupt cache {}", self.file.display());
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+
+        // The file list is still valid if no directory was modified since it was cached.
+        let listing_unchanged = cached.as_ref().is_some_and(|cached| {
+            cached.roots == roots
+                && cached
+                    .dirs
+                    .iter()
+                    .all(|(dir, mtime)| dir_mtime(&*self.source, dir) == *mtime)
+        });
+
+        let (paths, dirs) = match cached.as_ref().filter(|_| listing_unchanged) {
+            Some(cached) => (
+                cached
+                    .files
+                    .iter()
+                    .map(|file| (file.id.clone(), file.path.clone()))
+                    .collect(),
+                cached.dirs.clone(),
+            ),
+            None => walk(&self.source, &roots),
+        };
+
+        let mut previous = cached
+            .map(|cached| {
+                cached
+                    .files
+                    .into_iter()
+                    .map(|file| (file.path.clone(), file))
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+        let cached_count = previous.len();
+
+        let mut result = CachedEntries::default();
+        let mut failed = 0;
+        let mut files = Vec::with_capacity(paths.len());
+
+        for (id, path) in paths {
+            let Ok(metadata) = self.source.metadata(&path) else {
+                continue;
+            };
+            let size = metadata.len;
+            let mtime = metadata.modified.map(timestamp).unwrap_or_default();
+
+            let entry = match previous.remove(&path) {
+                Some(file) if file.size == size && file.mtime == mtime => {
+                    result.reused += usize::from(file.entry.is_some());
+                    file.entry
+                }
+                _ => {
+                    match DesktopEntry::from_path_with(
+                        &*self.source,
+                        path.clone(),
+                        locales.as_deref(),
+                    ) {
+                        Ok(mut entry) => {
+                            result.decoded += 1;
+                            entry.appid = id.clone();
+                            Some(entry)
+                        }
+                        Err(_) => {
+                            failed += 1;
+                            None
+                        }
+                    }
+                }
+            };
+
+            result.entries.extend(entry.clone());
+            files.push(CachedFile {
+                id,
+                path,
+                size,
+                mtime,
+                entry,
+            });
+        }
+
+        let unchanged =
+            listing_unchanged && result.decoded == 0 && failed == 0 && files.len() == cached_count;
+
+        if !unchanged {
+            let data = CacheData {
+                locales,
+                roots,
+                dirs,
+                files,
+            };
+
+            if let Err(why) = self.store(&data.encode()) {
+                log::warn!("failed to write cache {}: {why}", self.file.display());
+            }
+        }
+
+        result
+    }
+
+    /// Atomically replace the cache file with `bytes`.
+    fn store(&self, bytes: &[u8]) -> io::Result<()> {
+        if let Some(parent) = self.file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let mut temp = self.file.clone().into_os_string();
+        temp.push(format!(".{}.{nanos}.tmp", std::process::id()));
+
+        fs::write(&temp, bytes)?;
+        fs::rename(&temp, &self.file).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+    }
+}
+
+/// Walk `roots`, returning the ID and path of every desktop file along with the modification
+/// time of every directory that was walked.
+#[allow(clippy::type_complexity)]
+fn walk(
+    source: &Arc<dyn FileSystem>,
+    roots: &[PathBuf],
+) -> (Vec<(String, PathBuf)>, Vec<(PathBuf, Timestamp)>) {
+    let walked = Arc::new(Mutex::new(Vec::new()));
+
+    let paths = Iter::builder(roots.to_vec())
+        .filesystem(source.clone())
+        .on_directory({
+            let walked = walked.clone();
+            let source = source.clone();
+            // Taken before the directory is read, so that concurrent changes invalidate it.
+            move |dir, _| {
+                walked
+                    .lock()
+                    .unwrap()
+                    .push((dir.to_path_buf(), dir_mtime(&*source, dir)));
+                true
+            }
+        })
+        .build()
+        .with_ids()
+        .map(|(id, path)| (id.into(), path))
+        .collect();
+
+    let dirs = std::mem::take(&mut *walked.lock().unwrap());
+    (paths, dirs)
+}
+
+/// Seconds and nanoseconds since the Unix epoch.
+type Timestamp = (u64, u32);
+
+#[inline]
+fn timestamp(time: SystemTime) -> Timestamp {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (duration.as_secs(), duration.subsec_nanos())
+}
+
+/// Modification time of a directory, or zero if it does not exist.
+#[inline]
+fn dir_mtime(source: &dyn FileSystem, dir: &Path) -> Timestamp {
+    source
+        .metadata(dir)
+        .ok()
+        .and_then(|metadata| metadata.modified)
+        .map(timestamp)
+        .unwrap_or_default()
+}
+
+struct CachedFile {
+    id: String,
+    path: PathBuf,
+    size: u64,
+    mtime: Timestamp,
+    /// `None` if the file failed to decode.
+    entry: Option<DesktopEntry>,
+}
+
+struct CacheData {
+    locales: Option<Vec<String>>,
+    roots: Vec<PathBuf>,
+    dirs: Vec<(PathBuf, Timestamp)>,
+    files: Vec<CachedFile>,
+}
+
+impl CacheData {
+    fn encode(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::with_capacity(64 * 1024));
+        w.0.extend_from_slice(MAGIC);
+        w.u32(VERSION);
+
+        match &self.locales {
+            Some(locales) => {
+                w.0.push(1);
+                w.len(locales.len());
+                locales.iter().for_each(|locale| w.str(locale));
+            }
+            None => w.0.push(0),
+        }
+
+        w.len(self.roots.len());
+        self.roots.iter().for_each(|root| w.path(root));
+
+        w.len(self.dirs.len());
+        for (dir, mtime) in &self.dirs {
+            w.path(dir);
+            w.timestamp(*mtime);
+        }
+
+        w.len(self.files.len());
+        for file in &self.files {
+            w.str(&file.id);
+            w.path(&file.path);
+            w.u64(file.size);
+            w.timestamp(file.mtime);
+            match &file.entry {
+                Some(entry) => {
+                    w.0.push(1);
+                    w.entry(entry);
+                }
+                None => w.0.push(0),
+            }
+        }
+
+        let checksum = fnv1a(&w.0);
+        w.u64(checksum);
+        w.0
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let (body, checksum) = bytes.split_at_checked(bytes.len().checked_sub(8)?)?;
+        if fnv1a(body) != u64::from_le_bytes(checksum.try_into().ok()?) {
+            return None;
+        }
+
+        let mut r = Reader(body);
+        if r.bytes(MAGIC.len())? != MAGIC || r.u32()? != VERSION {
+            return None;
+        }
+
+        let locales = match r.u8()? {
+            0 => None,
+            1 => Some(r.list(Reader::string)?),
+            _ => return None,
+        };
+        let roots = r.list(Reader::path)?;
+        let dirs = r.list(|r| Some((r.path()?, r.timestamp()?)))?;
+        let files = r.list(|r| {
+            Some(CachedFile {
+                id: r.string()?,
+                path: r.path()?,
+                size: r.u64()?,
+                mtime: r.timestamp()?,
+                entry: match r.u8()? {
+                    0 => None,
+                    1 => Some(r.entry()?),
+                    _ => return None,
+                },
+            })
+        })?;
+
+        r.0.is_empty().then_some(CacheData {
+            locales,
+            roots,
+            dirs,
+            files,
+        })
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    fn path(&mut self, path: &Path) {
+        self.bytes(path.as_os_str().as_bytes());
+    }
+
+    fn timestamp(&mut self, (secs, nanos): Timestamp) {
+        self.u64(secs);
+        self.u32(nanos);
+    }
+
+    fn entry(&mut self, entry: &DesktopEntry) {
+        self.str(&entry.appid);
+        self.path(&entry.path);
+
+        match &entry.ubuntu_gettext_domain {
+            Some(domain) => {
+                self.0.push(1);
+                self.str(domain);
+            }
+            None => self.0.push(0),
+        }
+
+        self.len(entry.groups.0.len());
+        for (group_name, group) in &entry.groups.0 {
+            self.str(group_name);
+            self.len(group.0.len());
+            for (key, (value, locales)) in &group.0 {
+                self.str(key);
+                self.str(value);
+                self.len(locales.len());
+                for (locale, value) in locales {
+                    self.str(locale);
+                    self.str(value);
+                }
+            }
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (bytes, rest) = self.0.split_at_checked(len)?;
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        let len = self.u32()? as usize;
+        // Every element takes at least one byte, which bounds allocations on corrupt input.
+        (len <= self.0.len()).then_some(len)
+    }
+
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let len = self.len()?;
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            items.push(item(self)?);
+        }
+        Some(items)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.len()?;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn path(&mut self) -> Option<PathBuf> {
+        let len = self.len()?;
+        let bytes = self.bytes(len)?;
+        Some(PathBuf::from(OsStr::from_bytes(bytes)))
+    }
+
+    fn timestamp(&mut self) -> Option<Timestamp> {
+        Some((self.u64()?, self.u32()?))
+    }
+
+    fn entry(&mut self) -> Option<DesktopEntry> {
+        let appid = self.string()?;
+        let path = self.path()?;
+        let ubuntu_gettext_domain = match self.u8()? {
+            0 => None,
+            1 => Some(self.string()?),
+            _ => return None,
+        };
+
+        let mut groups = Groups::default();
+        for _ in 0..self.len()? {
+            let group_name = self.string()?;
+            let mut group = Group::default();
+            for _ in 0..self.len()? {
+                let key = self.string()?;
+                let value = self.string()?;
+                let locales = self
+                    .list(|r| Some((r.string()?, r.string()?)))?
+                    .into_iter()
+                    .collect::<LocaleMap>();
+                group.0.insert(key, (value, locales));
+            }
+            groups.0.insert(group_name, group);
+        }
+
+        Some(DesktopEntry {
+            appid,
+            groups,
+            path,
+            ubuntu_gettext_domain,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn cache_reuses_unchanged_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let apps = temp.path().join("applications");
+        fs::create_dir(&apps).unwrap();
+        fs::write(
+            apps.join("a.desktop"),
+            "[Desktop Entry]\nName=A\nName[fr]=Á\n",
+        )
+        .unwrap();
+        fs::write(apps.join("b.desktop"), "[Desktop Entry]\nName=B\n").unwrap();
+
+        let cache = Cache::new(temp.path().join("cache/entries.cache"));
+        let locales = ["fr"];
+
+        let first = cache.load_entries([apps.clone()], Some(&locales));
+        assert_eq!((first.reused, first.decoded), (0, 2));
+
+        let second = cache.load_entries([apps.clone()], Some(&locales));
+        assert_eq!((second.reused, second.decoded), (2, 0));
+        assert_eq!(second.entries, first.entries);
+        assert_eq!(second.entries[0].name(&locales).unwrap(), "Á");
+
+        // Modify a file and add another, which also modifies the directory.
+        fs::write(apps.join("b.desktop"), "[Desktop Entry]\nName=Bee\n").unwrap();
+        fs::write(apps.join("c.desktop"), "[Desktop Entry]\nName=C\n").unwrap();
+
+        let third = cache.load_entries([apps.clone()], Some(&locales));
+        assert_eq!((third.reused, third.decoded), (1, 2));
+        assert_eq!(third.entries[1].name(&[] as &[&str]).unwrap(), "Bee");
+
+        // Different locales invalidate the cache.
+        let fourth = cache.load_entries([apps.clone()], Some(&["de"]));
+        assert_eq!((fourth.reused, fourth.decoded), (0, 3));
+
+        // So does loading every locale.
+        let fifth = cache.load_entries([apps.clone()], None::<&[&str]>);
+        assert_eq!((fifth.reused, fifth.decoded), (0, 3));
+    }
+
+    #[test]
+    fn cache_remembers_failed_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let source = Arc::new(crate::MemoryFs::new());
+        source.add_file("/apps/a.desktop", "[Desktop Entry]\nName=A\n");
+        source.add_file("/apps/b.desktop", "Name=B\n");
+
+        let cache = Cache::new(temp.path().join("entries.cache")).filesystem(source.clone());
+        let apps = || [PathBuf::from("/apps")];
+
+        let first = cache.load_entries(apps(), None::<&[&str]>);
+        assert_eq!((first.reused, first.decoded), (0, 1));
+        assert_eq!(first.entries.len(), 1);
+
+        // The invalid file is not decoded again until it changes.
+        let second = cache.load_entries(apps(), None::<&[&str]>);
+        assert_eq!((second.reused, second.decoded), (1, 0));
+
+        source.add_file("/apps/b.desktop", "[Desktop Entry]\nName=B\n");
+        let third = cache.load_entries(apps(), None::<&[&str]>);
+        assert_eq!((third.reused, third.decoded), (1, 1));
+        assert_eq!(third.entries.len(), 2);
+    }
+
+    #[test]
+    fn cache_reads_entries_from_filesystem() {
+        let temp = tempfile::tempdir().unwrap();
+        let source = Arc::new(crate::MemoryFs::new());
+        source.add_file("/apps/a.desktop", "[Desktop Entry]\nName=A\n");
+        source.add_file("/apps/b.desktop", "[Desktop Entry]\nName=B\n");
+
+        let cache = Cache::new(temp.path().join("entries.cache")).filesystem(source.clone());
+        let apps = || [PathBuf::from("/apps")];
+
+        let first = cache.load_entries(apps(), None::<&[&str]>);
+        assert_eq!((first.reused, first.decoded), (0, 2));
+
+        source.add_file("/apps/b.desktop", "[Desktop Entry]\nName=Bee\n");
+        let second = cache.load_entries(apps(), None::<&[&str]>);
+        assert_eq!((second.reused, second.decoded), (1, 1));
+
+        source.remove("/apps/a.desktop");
+        let third = cache.load_entries(apps(), None::<&[&str]>);
+        assert_eq!((third.reused, third.decoded), (1, 0));
+        assert_eq!(third.entries.len(), 1);
+    }
+
+    #[test]
+    fn corrupt_cache_is_ignored() {
+        let temp = tempfile::tempdir().unwrap();
+        let apps = temp.path().join("applications");
+        fs::create_dir(&apps).unwrap();
+        fs::write(apps.join("a.desktop"), "[Desktop Entry]\nName=A\n").unwrap();
+
+        let cache = Cache::new(temp.path().join("entries.cache"));
+        cache.load_entries([apps.clone()], None::<&[&str]>);
+
+        let mut bytes = fs::read(cache.path()).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xff;
+        fs::write(cache.path(), &bytes).unwrap();
+
+        let loaded = cache.load_entries([apps.clone()], None::<&[&str]>);
+        assert_eq!((loaded.reused, loaded.decoded), (0, 1));
+
+        fs::write(cache.path(), &bytes[..middle]).unwrap();
+        let loaded = cache.load_entries([apps], None::<&[&str]>);
+        assert_eq!((loaded.reused, loaded.decoded), (0, 1));
+    }
+}
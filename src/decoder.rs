@@ -2,11 +2,13 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
-    fs::{self},
+    fmt::{self, Display, Formatter},
+    ops::Range,
     path::{Path, PathBuf},
 };
 
-use crate::{DesktopEntry, Group};
+use crate::span::{lines_with_offsets, Span};
+use crate::{DesktopEntry, FileSystem, Group, RealFs};
 use crate::{Groups, LocaleMap};
 use bstr::ByteSlice;
 use thiserror::Error;
@@ -19,18 +21,75 @@ pub enum DecodeError {
     Io(#[from] std::io::Error),
     #[error("MultipleGroupWithSameName")]
     MultipleGroupWithSameName,
-    #[error("KeyValueWithoutAGroup")]
-    KeyValueWithoutAGroup,
-    #[error("InvalidKey. Accepted: A-Za-z0-9")]
-    InvalidKey,
-    #[error("KeyDoesNotExist, this can happen when a localized key has no default value")]
-    KeyDoesNotExist,
-    #[error("InvalidValue")]
-    InvalidValue,
-    #[error("InvalidGroup")]
-    InvalidGroup,
-    #[error("InvalidEntry")]
-    InvalidEntry,
+    #[error("KeyValueWithoutAGroup at {0}")]
+    KeyValueWithoutAGroup(Box<Span>),
+    #[error("InvalidKey at {0}. Accepted: A-Za-z0-9")]
+    InvalidKey(Box<Span>),
+    #[error("KeyDoesNotExist at {0}, this can happen when a localized key has no default value")]
+    KeyDoesNotExist(Box<Span>),
+    #[error("InvalidValue at {0}")]
+    InvalidValue(Box<Span>),
+    #[error("InvalidGroup at {0}")]
+    InvalidGroup(Box<Span>),
+    #[error("InvalidEntry at {0}")]
+    InvalidEntry(Box<Span>),
+}
+
+impl DecodeError {
+    /// Location of the error in the decoded input, if it was caused by its contents.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            DecodeError::KeyValueWithoutAGroup(span)
+            | DecodeError::InvalidKey(span)
+            | DecodeError::KeyDoesNotExist(span)
+            | DecodeError::InvalidValue(span)
+            | DecodeError::InvalidGroup(span)
+            | DecodeError::InvalidEntry(span) => Some(span),
+            _ => None,
+        }
+    }
+
+    /// Render the error along with an excerpt of the offending line, if known.
+    pub fn render(&self) -> String {
+        match self.span() {
+            Some(span) => span.render(self),
+            None => format!("error: {self}\n"),
+        }
+    }
+}
+
+/// A problem which [`DesktopEntry::from_str_lossy`] recovered from.
+#[derive(Debug)]
+pub struct Warning {
+    pub error: DecodeError,
+    pub recovery: Recovery,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.error, self.recovery)
+    }
+}
+
+/// How an invalid line was dealt with when decoding lossily.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// The line was ignored.
+    SkippedLine,
+    /// The group header, and every entry up to the next group, were ignored.
+    SkippedGroup,
+    /// Unknown escape sequences were kept verbatim, and a trailing `\` was dropped.
+    RepairedValue,
+}
+
+impl Display for Recovery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Recovery::SkippedLine => "skipped line",
+            Recovery::SkippedGroup => "skipped group",
+            Recovery::RepairedValue => "repaired value",
+        })
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -40,10 +99,38 @@ pub enum Line<'a> {
     Comment(&'a str),
 }
 
+/// Constructor of a [`DecodeError`] variant along with the offending byte range of a line.
+type LineError = (fn(Box<Span>) -> DecodeError, Range<usize>);
+
+/// Parse a single line. Errors are reported as if it was the first line of an unnamed input.
+#[inline]
+pub fn parse_line(line: &str) -> Result<Line<'_>, DecodeError> {
+    parse_line_inner(line)
+        .map_err(|(error, range)| error(Box::new(Span::new(None, 1, 0, line, range))))
+}
+
+/// Parse a line located at `line_number`, starting at byte `offset` of the input.
+#[inline]
+pub(crate) fn parse_line_at<'a>(
+    path: Option<&Path>,
+    line_number: usize,
+    offset: usize,
+    line: &'a str,
+) -> Result<Line<'a>, DecodeError> {
+    let cursor = Cursor {
+        path,
+        line_number,
+        offset,
+        line,
+    };
+
+    parse_line_inner(line).map_err(|e| cursor.error(e))
+}
+
 #[inline]
-pub fn parse_line<'a>(line: &'a str) -> Result<Line<'a>, DecodeError> {
+fn parse_line_inner(line: &str) -> Result<Line<'_>, LineError> {
     if line.trim().is_empty() || line.starts_with('#') {
-        return Ok(Line::Comment(&line));
+        return Ok(Line::Comment(line));
     }
 
     let line_bytes = line.as_bytes();
@@ -52,9 +139,9 @@ pub fn parse_line<'a>(line: &'a str) -> Result<Line<'a>, DecodeError> {
     if line_bytes[0] == b'[' {
         if let Some(end) = memchr::memrchr(b']', &line_bytes[1..]) {
             let group_name = &line[1..end + 1];
-            return Ok(Line::Group(group_name));
+            Ok(Line::Group(group_name))
         } else {
-            return Err(DecodeError::InvalidGroup);
+            Err((DecodeError::InvalidGroup, 0..line.len()))
         }
     }
     // else, if entry
@@ -63,11 +150,33 @@ pub fn parse_line<'a>(line: &'a str) -> Result<Line<'a>, DecodeError> {
         let value = &line[delimiter + 1..];
 
         if key.is_empty() {
-            return Err(DecodeError::InvalidKey);
+            return Err((DecodeError::InvalidKey, 0..1));
         }
-        return Ok(Line::Entry(key, value));
+        Ok(Line::Entry(key, value))
     } else {
-        return Err(DecodeError::InvalidEntry);
+        Err((DecodeError::InvalidEntry, 0..line.len()))
+    }
+}
+
+/// Position of the line currently being decoded.
+#[derive(Clone, Copy)]
+struct Cursor<'a> {
+    path: Option<&'a Path>,
+    line_number: usize,
+    offset: usize,
+    line: &'a str,
+}
+
+impl Cursor<'_> {
+    #[inline]
+    fn error(&self, (error, range): LineError) -> DecodeError {
+        error(Box::new(Span::new(
+            self.path,
+            self.line_number,
+            self.offset,
+            self.line,
+            range,
+        )))
     }
 }
 
@@ -75,6 +184,7 @@ struct UnknownKey<'a> {
     key: &'a str,
     locale: String,
     value: String,
+    cursor: Cursor<'a>,
 }
 
 impl DesktopEntry {
@@ -86,97 +196,165 @@ impl DesktopEntry {
     where
         L: AsRef<str>,
     {
-        #[inline(never)]
-        fn inner<'a>(
-            path: PathBuf,
-            input: &'a str,
-            locales_filter: Option<Vec<&str>>,
-        ) -> Result<DesktopEntry, DecodeError> {
-            let path: PathBuf = path.into();
-
-            let appid = get_app_id(&path)?;
-
-            let mut groups = Groups::default();
-            let mut active_group: Option<ActiveGroup> = None;
-            let mut active_keys: Option<ActiveKeys> = None;
-            let mut ubuntu_gettext_domain = None;
-
-            let mut unknown_keys: Vec<UnknownKey> = Vec::new();
-
-            for line in input.lines() {
-                process_line(
-                    line,
-                    &mut groups,
-                    &mut active_group,
-                    &mut active_keys,
-                    &mut ubuntu_gettext_domain,
-                    locales_filter.as_deref(),
-                    &mut unknown_keys,
-                )?;
-            }
-            
-            if let Some(active_keys) = active_keys.take() {
-                match &mut active_group {
-                    Some(active_group) => {
-                        active_group.group.0.insert(
-                            active_keys.key_name,
-                            (active_keys.default_value, active_keys.locales),
-                        );
-                    }
-                    None => return Err(DecodeError::KeyValueWithoutAGroup),
-                }
-            }
-
-            // insert keys which have no group
-            for unknown_key in unknown_keys.drain(..) {
-                match &mut active_group {
-                    Some(active_group) => match active_group.group.0.get_mut(unknown_key.key) {
-                        Some((_, locale_map)) => {
-                            locale_map.insert(unknown_key.locale, unknown_key.value);
-                        }
-                        None => return Err(DecodeError::KeyDoesNotExist),
-                    },
-                    None => return Err(DecodeError::KeyDoesNotExist),
-                }
-            }
-
-            if let Some(mut group) = active_group.take() {
-                groups
-                    .0
-                    .entry(group.group_name)
-                    .or_insert_with(|| Group::default())
-                    .0
-                    .append(&mut group.group.0);
-            }
-
-            Ok(DesktopEntry {
-                appid,
-                groups,
-                path,
-                ubuntu_gettext_domain,
-            })
-        }
+        let locales_filter = locales_filter.map(add_generic_locales);
+        decode(path.into(), input, locales_filter.as_deref(), None)
+    }
 
-        inner(path.into(), input, locales_filter.map(add_generic_locales))
+    /// Like [`DesktopEntry::from_str`], but invalid lines are skipped or repaired instead of
+    /// failing the whole entry. Every recovered error is returned as a [`Warning`].
+    ///
+    /// Fails only if the path does not contain a valid app ID.
+    pub fn from_str_lossy<L>(
+        path: impl Into<PathBuf>,
+        input: &str,
+        locales_filter: Option<&[L]>,
+    ) -> Result<(DesktopEntry, Vec<Warning>), DecodeError>
+    where
+        L: AsRef<str>,
+    {
+        let locales_filter = locales_filter.map(add_generic_locales);
+        let mut warnings = Vec::new();
+        let entry = decode(
+            path.into(),
+            input,
+            locales_filter.as_deref(),
+            Some(&mut warnings),
+        )?;
+        Ok((entry, warnings))
     }
 
     /// Return an owned [`DesktopEntry`]
+    ///
+    /// Its [`DesktopEntry::appid`] is guessed from the path alone, which may differ from the ID
+    /// of the specification for nested directories. [`crate::Iter::entries`] sets the latter.
     #[inline]
     pub fn from_path<L>(
         path: impl Into<PathBuf>,
         locales_filter: Option<&[L]>,
     ) -> Result<DesktopEntry, DecodeError>
+    where
+        L: AsRef<str>,
+    {
+        Self::from_path_with(&RealFs, path, locales_filter)
+    }
+
+    /// Like [`DesktopEntry::from_path`], reading from the given file system.
+    #[inline]
+    pub fn from_path_with<L>(
+        fs: &dyn FileSystem,
+        path: impl Into<PathBuf>,
+        locales_filter: Option<&[L]>,
+    ) -> Result<DesktopEntry, DecodeError>
     where
         L: AsRef<str>,
     {
         let path: PathBuf = path.into();
-        let input = fs::read_to_string(&path)?;
+        let input = fs.read_to_string(&path)?;
         Self::from_str(path, &input, locales_filter)
     }
+
+    /// Return an owned [`DesktopEntry`], recovering from invalid lines as
+    /// [`DesktopEntry::from_str_lossy`] does.
+    #[inline]
+    pub fn from_path_lossy<L>(
+        path: impl Into<PathBuf>,
+        locales_filter: Option<&[L]>,
+    ) -> Result<(DesktopEntry, Vec<Warning>), DecodeError>
+    where
+        L: AsRef<str>,
+    {
+        Self::from_path_lossy_with(&RealFs, path, locales_filter)
+    }
+
+    /// Like [`DesktopEntry::from_path_lossy`], reading from the given file system.
+    #[inline]
+    pub fn from_path_lossy_with<L>(
+        fs: &dyn FileSystem,
+        path: impl Into<PathBuf>,
+        locales_filter: Option<&[L]>,
+    ) -> Result<(DesktopEntry, Vec<Warning>), DecodeError>
+    where
+        L: AsRef<str>,
+    {
+        let path: PathBuf = path.into();
+        let input = fs.read_to_string(&path)?;
+        Self::from_str_lossy(path, &input, locales_filter)
+    }
 }
 
+/// Decode a desktop entry. Errors are recorded in `warnings` and recovered from if it is given.
+#[inline(never)]
+fn decode(
+    path: PathBuf,
+    input: &str,
+    locales_filter: Option<&[&str]>,
+    mut warnings: Option<&mut Vec<Warning>>,
+) -> Result<DesktopEntry, DecodeError> {
+    let appid = get_app_id(&path)?;
+
+    let mut groups = Groups::default();
+    let mut active_group: Option<ActiveGroup> = None;
+    let mut active_keys: Option<ActiveKeys> = None;
+    let mut ubuntu_gettext_domain = None;
+
+    let mut unknown_keys: Vec<UnknownKey> = Vec::new();
+
+    for (line_number, (offset, line)) in lines_with_offsets(input).enumerate() {
+        let cursor = Cursor {
+            path: Some(&path),
+            line_number: line_number + 1,
+            offset,
+            line,
+        };
+
+        process_line(
+            cursor,
+            &mut groups,
+            &mut active_group,
+            &mut active_keys,
+            &mut ubuntu_gettext_domain,
+            locales_filter,
+            &mut unknown_keys,
+            warnings.as_deref_mut(),
+        )?;
+    }
+
+    flush_active_keys(&mut active_group, &mut active_keys);
+    insert_unknown_keys(&mut active_group, &mut unknown_keys, warnings)?;
+    flush_active_group(&mut groups, &mut active_group);
+
+    Ok(DesktopEntry {
+        appid,
+        groups,
+        path,
+        ubuntu_gettext_domain,
+    })
+}
+
+/// Record `error` as a warning if decoding lossily, or fail with it otherwise.
+#[inline]
+fn recover(
+    warnings: Option<&mut Vec<Warning>>,
+    error: DecodeError,
+    recovery: Recovery,
+) -> Result<(), DecodeError> {
+    match warnings {
+        Some(warnings) => {
+            warnings.push(Warning { error, recovery });
+            Ok(())
+        }
+        None => Err(error),
+    }
+}
+
+/// Guess the ID of an entry from its path alone, from whatever follows the last
+/// `applications` component, or else its file stem.
+///
+/// This is a fallback rather than the ID of the specification, which is relative to the data
+/// directory the file was found in and cannot be known from the path. Entries found by
+/// [`crate::Iter`] use [`crate::DesktopFileId`] instead.
 #[inline]
-fn get_app_id<P: AsRef<Path> + ?Sized>(path: &P) -> Result<String, DecodeError> {
+pub(crate) fn get_app_id<P: AsRef<Path> + ?Sized>(path: &P) -> Result<String, DecodeError> {
     let path_as_bytes = path
         .as_ref()
         .as_os_str()
@@ -204,6 +382,8 @@ fn get_app_id<P: AsRef<Path> + ?Sized>(path: &P) -> Result<String, DecodeError>
 struct ActiveGroup {
     group_name: String,
     group: Group,
+    /// Set for groups with an invalid header, whose entries are dropped.
+    discard: bool,
 }
 
 #[derive(Debug)]
@@ -213,59 +393,122 @@ struct ActiveKeys {
     locales: LocaleMap,
 }
 
+/// Move the key currently being decoded into the active group.
+#[inline]
+fn flush_active_keys(active_group: &mut Option<ActiveGroup>, active_keys: &mut Option<ActiveKeys>) {
+    if let (Some(active_group), Some(active_keys)) = (active_group, active_keys.take()) {
+        active_group.group.0.insert(
+            active_keys.key_name,
+            (active_keys.default_value, active_keys.locales),
+        );
+    }
+}
+
+/// Move the active group into `groups`, unless its header was invalid.
+#[inline]
+fn flush_active_group(groups: &mut Groups, active_group: &mut Option<ActiveGroup>) {
+    if let Some(mut group) = active_group.take().filter(|group| !group.discard) {
+        groups
+            .0
+            .entry(group.group_name)
+            .or_default()
+            .0
+            .append(&mut group.group.0);
+    }
+}
+
+/// Insert localized keys which were decoded before their default value.
+#[inline]
+fn insert_unknown_keys(
+    active_group: &mut Option<ActiveGroup>,
+    unknown_keys: &mut Vec<UnknownKey<'_>>,
+    mut warnings: Option<&mut Vec<Warning>>,
+) -> Result<(), DecodeError> {
+    if active_group.as_ref().is_some_and(|group| group.discard) {
+        unknown_keys.clear();
+        return Ok(());
+    }
+
+    for unknown_key in unknown_keys.drain(..) {
+        match active_group
+            .as_mut()
+            .and_then(|active_group| active_group.group.0.get_mut(unknown_key.key))
+        {
+            Some((_, locale_map)) => {
+                locale_map.insert(unknown_key.locale, unknown_key.value);
+            }
+            None => {
+                let cursor = unknown_key.cursor;
+                let key_len = cursor.line.find('=').unwrap_or(cursor.line.len());
+                let error = cursor.error((DecodeError::KeyDoesNotExist, 0..key_len));
+                recover(warnings.as_deref_mut(), error, Recovery::SkippedLine)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 #[inline(never)]
 fn process_line<'a>(
-    line: &'a str,
+    cursor: Cursor<'a>,
     groups: &mut Groups,
     active_group: &mut Option<ActiveGroup>,
     active_keys: &mut Option<ActiveKeys>,
     ubuntu_gettext_domain: &mut Option<String>,
     locales_filter: Option<&[&str]>,
     unknown_keys: &mut Vec<UnknownKey<'a>>,
+    mut warnings: Option<&mut Vec<Warning>>,
 ) -> Result<(), DecodeError> {
-    match parse_line(line)? {
-        Line::Group(group_name) => {
-            // insert keys which have no group
-            for unknown_key in unknown_keys.drain(..) {
-                match active_group {
-                    Some(active_group) => match active_group.group.0.get_mut(unknown_key.key) {
-                        Some((_, locale_map)) => {
-                            locale_map.insert(unknown_key.locale, unknown_key.value);
-                        }
-                        None => return Err(DecodeError::KeyDoesNotExist),
-                    },
-                    None => return Err(DecodeError::KeyDoesNotExist),
-                }
-            }
+    let line = match parse_line_inner(cursor.line) {
+        Ok(line) => line,
+        Err(error) => {
+            let error = cursor.error(error);
 
-            if let Some(active_keys) = active_keys.take() {
-                match active_group {
-                    Some(active_group) => {
-                        active_group.group.0.insert(
-                            active_keys.key_name,
-                            (active_keys.default_value, active_keys.locales),
-                        );
-                    }
-                    None => return Err(DecodeError::KeyValueWithoutAGroup),
-                }
+            if !matches!(error, DecodeError::InvalidGroup(_)) {
+                return recover(warnings, error, Recovery::SkippedLine);
             }
 
-            if let Some(mut group) = active_group.take() {
-                groups
-                    .0
-                    .entry(group.group_name)
-                    .or_insert_with(|| Group::default())
-                    .0
-                    .append(&mut group.group.0);
-            }
+            flush_active_keys(active_group, active_keys);
+            insert_unknown_keys(active_group, unknown_keys, warnings.as_deref_mut())?;
+            flush_active_group(groups, active_group);
+            recover(warnings, error, Recovery::SkippedGroup)?;
+
+            active_group.replace(ActiveGroup {
+                group_name: String::new(),
+                group: Group::default(),
+                discard: true,
+            });
+
+            return Ok(());
+        }
+    };
+
+    match line {
+        Line::Group(group_name) => {
+            flush_active_keys(active_group, active_keys);
+            insert_unknown_keys(active_group, unknown_keys, warnings)?;
+            flush_active_group(groups, active_group);
 
             active_group.replace(ActiveGroup {
                 group_name: group_name.to_string(),
                 group: Group::default(),
+                discard: false,
             });
         }
         Line::Entry(key, value) => {
-            let value = format_value(value)?;
+            let value_offset = key.len() + 1;
+            let mut repairs = warnings.as_ref().map(|_| Vec::new());
+            let value = format_value(value, repairs.as_mut()).map_err(|(error, range)| {
+                cursor.error((error, value_offset + range.start..value_offset + range.end))
+            })?;
+
+            for range in repairs.into_iter().flatten() {
+                let range = value_offset + range.start..value_offset + range.end;
+                let error = cursor.error((DecodeError::InvalidValue, range));
+                recover(warnings.as_deref_mut(), error, Recovery::RepairedValue)?;
+            }
 
             // if locale
             if key.as_bytes()[key.len() - 1] == b']' {
@@ -275,7 +518,7 @@ fn process_line<'a>(
                     let key = &key[..start];
 
                     match locales_filter {
-                        Some(locales_filter) if !locales_filter.iter().any(|l| *l == locale) => {
+                        Some(locales_filter) if !locales_filter.contains(&locale) => {
                             return Ok(());
                         }
                         _ => (),
@@ -293,6 +536,7 @@ fn process_line<'a>(
                             key,
                             locale: locale.to_string(),
                             value,
+                            cursor,
                         });
                     }
 
@@ -305,17 +549,12 @@ fn process_line<'a>(
                 return Ok(());
             }
 
-            if let Some(active_keys) = active_keys.take() {
-                match active_group {
-                    Some(active_group) => {
-                        active_group.group.0.insert(
-                            active_keys.key_name,
-                            (active_keys.default_value, active_keys.locales),
-                        );
-                    }
-                    None => return Err(DecodeError::KeyValueWithoutAGroup),
-                }
+            if active_group.is_none() {
+                let error = cursor.error((DecodeError::KeyValueWithoutAGroup, 0..key.len()));
+                return recover(warnings, error, Recovery::SkippedLine);
             }
+
+            flush_active_keys(active_group, active_keys);
             active_keys.replace(ActiveKeys {
                 // todo: verify that the key only contains A-Za-z0-9 ?
                 key_name: key.trim().to_string(),
@@ -329,12 +568,17 @@ fn process_line<'a>(
 }
 
 // https://specifications.freedesktop.org/desktop-entry-spec/latest/value-types.html
+//
+// Invalid escape sequences are an error, unless `repairs` is given. Then their ranges are
+// recorded and unknown escapes are kept verbatim, while a trailing `\` is dropped.
 #[inline]
-fn format_value(input: &str) -> Result<String, DecodeError> {
-    let input = if let Some(input) = input.strip_prefix(" ") {
-        input
-    } else {
-        input
+fn format_value(
+    input: &str,
+    mut repairs: Option<&mut Vec<Range<usize>>>,
+) -> Result<String, LineError> {
+    let (input, stripped) = match input.strip_prefix(' ') {
+        Some(input) => (input, 1),
+        None => (input, 0),
     };
 
     let mut res = String::with_capacity(input.len());
@@ -347,15 +591,23 @@ fn format_value(input: &str) -> Result<String, DecodeError> {
             continue;
         }
 
-        // when there is an \ at the end
-        if input.len() <= i + 1 {
-            return Err(DecodeError::InvalidValue);
-        }
-
         if last < i {
             res.push_str(&input[last..i]);
         }
 
+        // when there is an \ at the end
+        if input.len() <= i + 1 {
+            let range = stripped + i..stripped + i + 1;
+            match repairs.as_deref_mut() {
+                Some(repairs) => {
+                    repairs.push(range);
+                    last = input.len();
+                    break;
+                }
+                None => return Err((DecodeError::InvalidValue, range)),
+            }
+        }
+
         last = i + 2;
 
         match input.as_bytes()[i + 1] {
@@ -365,7 +617,19 @@ fn format_value(input: &str) -> Result<String, DecodeError> {
             b'r' => res.push('\r'),
             b'\\' => res.push('\\'),
             _ => {
-                return Err(DecodeError::InvalidValue);
+                let end = input[i + 1..]
+                    .chars()
+                    .next()
+                    .map_or(i + 1, |c| i + 1 + c.len_utf8());
+                let range = stripped + i..stripped + end;
+                match repairs.as_deref_mut() {
+                    Some(repairs) => {
+                        repairs.push(range);
+                        res.push_str(&input[i..end]);
+                        last = end;
+                    }
+                    None => return Err((DecodeError::InvalidValue, range)),
+                }
             }
         }
     }
@@ -397,7 +661,9 @@ fn add_generic_locales<L: AsRef<str>>(locales: &[L]) -> Vec<&str> {
 
 #[cfg(test)]
 mod test {
-    use crate::{decoder::Line, parse_line};
+    use std::path::Path;
+
+    use crate::{decoder::Line, parse_line, DecodeError, DesktopEntry, Recovery};
 
     #[test]
     fn test_parse_empty_comment() {
@@ -440,4 +706,72 @@ mod test {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_invalid_value_span() {
+        let input = "[Desktop Entry]\nName=Foo\nExec= foo \\q\n";
+        let error = DesktopEntry::from_str("app.desktop", input, None::<&[&str]>).unwrap_err();
+
+        let DecodeError::InvalidValue(span) = &error else {
+            panic!("unexpected error: {error:?}");
+        };
+
+        assert_eq!(span.path.as_deref(), Some(Path::new("app.desktop")));
+        assert_eq!((span.line, span.column), (3, 11));
+        assert_eq!(&input[span.bytes.clone()], "\\q");
+        assert_eq!(span.text, "\\q");
+        assert_eq!(
+            error.render(),
+            "error: InvalidValue at app.desktop:3:11\n  |\n3 | Exec= foo \\q\n  |           ^^\n"
+        );
+    }
+
+    #[test]
+    fn test_key_without_group_span() {
+        let input = "# comment\nName=Foo\n";
+        let error = DesktopEntry::from_str("app.desktop", input, None::<&[&str]>).unwrap_err();
+
+        let span = error.span().unwrap();
+        assert!(matches!(error, DecodeError::KeyValueWithoutAGroup(_)));
+        assert_eq!((span.line, span.column, span.text.as_str()), (2, 1, "Name"));
+    }
+
+    #[test]
+    fn test_lossy_recovers_invalid_lines() {
+        let input = "[Desktop Entry]\n\
+            Name=Foo\n\
+            Comment=Trailing\\\n\
+            Keywords=a\\qb\n\
+            missing delimiter\n\
+            [Broken Group\n\
+            Exec=ignored\n\
+            [Desktop Action new]\n\
+            Exec=foo --new\n";
+
+        assert!(DesktopEntry::from_str("app.desktop", input, None::<&[&str]>).is_err());
+
+        let (entry, warnings) =
+            DesktopEntry::from_str_lossy("app.desktop", input, None::<&[&str]>).unwrap();
+
+        assert_eq!(entry.desktop_entry("Name"), Some("Foo"));
+        assert_eq!(entry.desktop_entry("Comment"), Some("Trailing"));
+        assert_eq!(entry.desktop_entry("Keywords"), Some("a\\qb"));
+        assert_eq!(entry.exec(), None);
+        assert_eq!(entry.action_exec("new"), Some("foo --new"));
+
+        let recovered = warnings
+            .iter()
+            .map(|warning| (warning.error.span().unwrap().line, warning.recovery))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            recovered,
+            [
+                (3, Recovery::RepairedValue),
+                (4, Recovery::RepairedValue),
+                (5, Recovery::SkippedLine),
+                (6, Recovery::SkippedGroup),
+            ]
+        );
+    }
 }
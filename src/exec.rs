@@ -1,6 +1,13 @@
 // Copyright 2021 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+use std::{
+    env,
+    ffi::{CString, OsStr},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
 use crate::DesktopEntry;
 use thiserror::Error;
 
@@ -16,6 +23,18 @@ pub enum ExecError {
     ExecFieldNotFound,
 }
 
+#[derive(Debug, Error)]
+pub enum ResolveError {
+    #[error(transparent)]
+    Exec(#[from] ExecError),
+
+    #[error("program '{0}' was not found")]
+    NotFound(String),
+
+    #[error("{} is not executable", .0.display())]
+    NotExecutable(PathBuf),
+}
+
 impl DesktopEntry {
     #[inline]
     pub fn parse_exec(&self) -> Result<Vec<String>, ExecError> {
@@ -63,8 +82,8 @@ impl DesktopEntry {
         L: AsRef<str>,
     {
         #[inline(never)]
-        fn inner<'a>(
-            this: &'a DesktopEntry,
+        fn inner(
+            this: &DesktopEntry,
             exec: Option<&str>,
             uris: &[&str],
             locales: &mut dyn Iterator<Item = &str>,
@@ -137,6 +156,81 @@ impl DesktopEntry {
     }
 }
 
+impl DesktopEntry {
+    /// The program which must be installed for this entry to work: `TryExec` if set,
+    /// otherwise the first token of `Exec`.
+    pub fn program(&self) -> Result<String, ExecError> {
+        match self.try_exec() {
+            Some(try_exec) => Ok(try_exec.to_owned()),
+            None => Ok(self.parse_exec()?.swap_remove(0)),
+        }
+    }
+
+    /// Resolve [`DesktopEntry::program`] to an executable file, searching `search_path` if it is
+    /// not a path. `search_path` is in the format of the `PATH` variable.
+    #[inline]
+    pub fn resolve_executable(&self, search_path: &OsStr) -> Result<PathBuf, ResolveError> {
+        find_executable(&self.program()?, search_path)
+    }
+
+    /// Like [`DesktopEntry::resolve_executable`], searching the `PATH` of this process.
+    #[inline]
+    pub fn resolve_executable_from_env(&self) -> Result<PathBuf, ResolveError> {
+        self.resolve_executable(&env::var_os("PATH").unwrap_or_default())
+    }
+}
+
+/// Resolve `program` to a file which the current user may execute. Names without a `/` are
+/// searched for in `search_path`, which is in the format of the `PATH` variable.
+pub fn find_executable(program: &str, search_path: &OsStr) -> Result<PathBuf, ResolveError> {
+    if program.contains('/') {
+        let path = Path::new(program);
+
+        return if !path.is_file() {
+            Err(ResolveError::NotFound(program.to_owned()))
+        } else if is_executable(path) {
+            Ok(path.to_path_buf())
+        } else {
+            Err(ResolveError::NotExecutable(path.to_path_buf()))
+        };
+    }
+
+    let mut not_executable = None;
+
+    for dir in env::split_paths(search_path) {
+        // An empty entry would search the working directory
+        if dir.as_os_str().is_empty() {
+            continue;
+        }
+
+        let path = dir.join(program);
+        if !path.is_file() {
+            continue;
+        }
+
+        if is_executable(&path) {
+            return Ok(path);
+        }
+
+        not_executable.get_or_insert(path);
+    }
+
+    Err(match not_executable {
+        Some(path) => ResolveError::NotExecutable(path),
+        None => ResolveError::NotFound(program.to_owned()),
+    })
+}
+
+/// Whether the effective user of this process may execute `path`.
+fn is_executable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    // SAFETY: `path` is a valid NUL-terminated string which outlives the call.
+    unsafe { libc::faccessat(libc::AT_FDCWD, path.as_ptr(), libc::X_OK, libc::AT_EACCESS) == 0 }
+}
+
 // either a command line argument or a field-code as described
 // in https://specifications.freedesktop.org/desktop-entry-spec/desktop-entry-spec-latest.html#exec-variables
 enum ArgOrFieldCode<'a> {
@@ -184,11 +278,11 @@ impl<'a> TryFrom<&'a str> for ArgOrFieldCode<'a> {
 #[cfg(test)]
 mod test {
 
-    use std::path::PathBuf;
+    use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
 
-    use crate::{DesktopEntry, get_languages_from_env};
+    use crate::{get_languages_from_env, DesktopEntry};
 
-    use super::ExecError;
+    use super::{ExecError, ResolveError};
 
     #[test]
     fn should_return_unmatched_quote_error() {
@@ -240,6 +334,47 @@ mod test {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn should_resolve_executable() {
+        let temp = tempfile::tempdir().unwrap();
+        let bin = temp.path().join("bin");
+        let other = temp.path().join("other");
+        fs::create_dir_all(&bin).unwrap();
+        fs::create_dir_all(&other).unwrap();
+
+        // The first directory only has a file which is not executable
+        fs::write(bin.join("app"), "").unwrap();
+        fs::set_permissions(bin.join("app"), fs::Permissions::from_mode(0o644)).unwrap();
+        fs::write(other.join("app"), "").unwrap();
+        fs::set_permissions(other.join("app"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(bin.join("data"), "").unwrap();
+        fs::set_permissions(bin.join("data"), fs::Permissions::from_mode(0o644)).unwrap();
+
+        let search_path = std::env::join_paths([&bin, &other]).unwrap();
+
+        let entry = |keys: &str| {
+            let input = format!("[Desktop Entry]\n{keys}");
+            DesktopEntry::from_str("app.desktop", &input, None::<&[&str]>).unwrap()
+        };
+
+        assert_eq!(
+            entry("Exec=app --new-window %U")
+                .resolve_executable(&search_path)
+                .unwrap(),
+            other.join("app")
+        );
+        assert!(matches!(
+            entry("TryExec=missing\nExec=app").resolve_executable(&search_path),
+            Err(ResolveError::NotFound(name)) if name == "missing"
+        ));
+
+        let data = bin.join("data");
+        assert!(matches!(
+            entry(&format!("Exec={}", data.display())).resolve_executable(&search_path),
+            Err(ResolveError::NotExecutable(path)) if path == data
+        ));
+    }
+
     #[test]
     #[ignore = "Needs a desktop environment with nvim installed, run locally only"]
     fn should_parse_exec_with_field_codes() {
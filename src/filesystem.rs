@@ -0,0 +1,390 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::RwLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The file system which entries are discovered and loaded from.
+///
+/// [`RealFs`] uses [`std::fs`], and [`MemoryFs`] keeps files in memory, so that discovery can be
+/// tested deterministically, or entries read from archives and container images.
+pub trait FileSystem: Debug + Send + Sync {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Children of a directory, along with their types. Symbolic links are not followed.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<(PathBuf, FileKind)>>;
+
+    /// Metadata of a file, following symbolic links.
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    #[inline]
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub kind: FileKind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl From<fs::FileType> for FileKind {
+    #[inline]
+    fn from(file_type: fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_file() {
+            FileKind::File
+        } else {
+            FileKind::Other
+        }
+    }
+}
+
+/// The file system of the host, through [`std::fs`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl FileSystem for RealFs {
+    #[inline]
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<(PathBuf, FileKind)>> {
+        Ok(fs::read_dir(path)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| Some((entry.path(), entry.file_type().ok()?.into())))
+            .collect())
+    }
+
+    #[inline]
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileMetadata {
+            kind: metadata.file_type().into(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    #[inline]
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        path.canonicalize()
+    }
+
+    #[inline]
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+/// Symbolic links followed before giving up, as `ELOOP` does.
+const MAX_SYMLINKS: usize = 40;
+
+/// A file system held in memory. Paths must be absolute.
+///
+/// Every change advances a clock by one second, which becomes the modification time of the
+/// changed file and its parent directory.
+///
+/// ```
+/// use std::sync::Arc;
+/// use freedesktop_desktop_entry::{Iter, MemoryFs};
+///
+/// let fs = Arc::new(MemoryFs::new());
+/// fs.add_file("/usr/share/applications/app.desktop", "[Desktop Entry]\nName=App\n");
+///
+/// let entries = Iter::builder(["/usr/share/applications".into()])
+///     .filesystem(fs)
+///     .build()
+///     .entries(None::<&[&str]>)
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(entries[0].appid, "app");
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    state: RwLock<MemoryState>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    nodes: BTreeMap<PathBuf, Node>,
+    clock: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    kind: NodeKind,
+    modified: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+enum NodeKind {
+    File(Vec<u8>),
+    Dir,
+    Symlink(PathBuf),
+}
+
+impl MemoryFs {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create or replace a file, creating its parent directories.
+    pub fn add_file(&self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) {
+        self.insert(path.as_ref(), NodeKind::File(contents.into()));
+    }
+
+    /// Create a directory and its parents.
+    pub fn add_dir(&self, path: impl AsRef<Path>) {
+        self.insert(path.as_ref(), NodeKind::Dir);
+    }
+
+    /// Create a symbolic link to `target`, which is relative to the parent of the link unless
+    /// it is absolute.
+    pub fn add_symlink(&self, path: impl AsRef<Path>, target: impl Into<PathBuf>) {
+        self.insert(path.as_ref(), NodeKind::Symlink(target.into()));
+    }
+
+    /// Remove a file, link or directory, along with everything within it.
+    pub fn remove(&self, path: impl AsRef<Path>) {
+        let path = normalize(path.as_ref());
+        let mut state = self.state.write().unwrap();
+        state.nodes.retain(|node, _| !node.starts_with(&path));
+        state.touch_parent(&path);
+    }
+
+    fn insert(&self, path: &Path, kind: NodeKind) {
+        let path = normalize(path);
+        let mut state = self.state.write().unwrap();
+        let modified = state.tick();
+
+        for ancestor in path.ancestors().skip(1) {
+            state
+                .nodes
+                .entry(ancestor.to_path_buf())
+                .or_insert_with(|| Node {
+                    kind: NodeKind::Dir,
+                    modified,
+                });
+        }
+
+        state.touch_parent(&path);
+        state.nodes.insert(path, Node { kind, modified });
+    }
+}
+
+impl MemoryState {
+    #[inline]
+    fn tick(&mut self) -> SystemTime {
+        self.clock += 1;
+        UNIX_EPOCH + Duration::from_secs(self.clock)
+    }
+
+    fn touch_parent(&mut self, path: &Path) {
+        let modified = self.tick();
+        if let Some(parent) = path.parent().and_then(|parent| self.nodes.get_mut(parent)) {
+            parent.modified = modified;
+        }
+    }
+
+    /// Follow every symbolic link in `path`.
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut pending = normalize(path)
+            .components()
+            .map(|c| c.as_os_str().to_owned())
+            .collect::<Vec<_>>();
+        pending.reverse();
+
+        let mut resolved = PathBuf::new();
+        let mut links = 0;
+
+        while let Some(component) = pending.pop() {
+            resolved.push(&component);
+
+            let Some(node) = self.nodes.get(&resolved) else {
+                if resolved.parent().is_none() {
+                    continue;
+                }
+
+                return Err(io::ErrorKind::NotFound.into());
+            };
+
+            if let NodeKind::Symlink(target) = &node.kind {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(io::Error::other("too many levels of symbolic links"));
+                }
+
+                resolved.pop();
+                let target = normalize(&resolved.join(target));
+                pending.extend(target.components().rev().map(|c| c.as_os_str().to_owned()));
+                resolved = PathBuf::new();
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    #[inline]
+    fn node(&self, path: &Path) -> io::Result<(PathBuf, &Node)> {
+        let path = self.resolve(path)?;
+        match self.nodes.get(&path) {
+            Some(node) => Ok((path, node)),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let state = self.state.read().unwrap();
+        match &state.node(path)?.1.kind {
+            NodeKind::File(contents) => Ok(contents.clone()),
+            _ => Err(io::Error::other("is a directory")),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<(PathBuf, FileKind)>> {
+        let state = self.state.read().unwrap();
+        let (resolved, node) = state.node(path)?;
+
+        if !matches!(node.kind, NodeKind::Dir) {
+            return Err(io::Error::other("not a directory"));
+        }
+
+        // Children are listed under the path as given, as `std::fs::read_dir` does.
+        let path = normalize(path);
+        Ok(state
+            .nodes
+            .range(resolved.clone()..)
+            .skip(1)
+            .take_while(|(child, _)| child.starts_with(&resolved))
+            .filter(|(child, _)| child.parent() == Some(resolved.as_path()))
+            .map(|(child, node)| {
+                let kind = match node.kind {
+                    NodeKind::File(_) => FileKind::File,
+                    NodeKind::Dir => FileKind::Dir,
+                    NodeKind::Symlink(_) => FileKind::Symlink,
+                };
+
+                (path.join(child.file_name().unwrap_or_default()), kind)
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let state = self.state.read().unwrap();
+        let (_, node) = state.node(path)?;
+
+        let (kind, len) = match &node.kind {
+            NodeKind::File(contents) => (FileKind::File, contents.len() as u64),
+            NodeKind::Dir => (FileKind::Dir, 0),
+            NodeKind::Symlink(_) => (FileKind::Symlink, 0),
+        };
+
+        Ok(FileMetadata {
+            kind,
+            len,
+            modified: Some(node.modified),
+        })
+    }
+
+    #[inline]
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let state = self.state.read().unwrap();
+        state.node(path).map(|(path, _)| path)
+    }
+}
+
+/// Remove `.` and resolve `..` components lexically.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => (),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::Path};
+
+    use super::{FileKind, FileSystem, MemoryFs};
+
+    #[test]
+    fn memory_fs_follows_links() {
+        let fs = MemoryFs::new();
+        fs.add_file("/usr/share/applications/app.desktop", "[Desktop Entry]\n");
+        fs.add_symlink("/usr/share/applications/link", "../applications");
+        fs.add_symlink("/loop", "/loop");
+
+        assert_eq!(
+            fs.read_dir(Path::new("/usr/share/applications")).unwrap(),
+            [
+                (
+                    Path::new("/usr/share/applications/app.desktop").to_path_buf(),
+                    FileKind::File
+                ),
+                (
+                    Path::new("/usr/share/applications/link").to_path_buf(),
+                    FileKind::Symlink
+                ),
+            ]
+        );
+        assert_eq!(
+            fs.canonicalize(Path::new("/usr/share/applications/link/link/app.desktop"))
+                .unwrap(),
+            Path::new("/usr/share/applications/app.desktop")
+        );
+        assert_eq!(
+            fs.metadata(Path::new("/usr/share/applications/link"))
+                .unwrap()
+                .kind,
+            FileKind::Dir
+        );
+        assert!(fs.metadata(Path::new("/loop")).is_err());
+
+        let before = fs.metadata(Path::new("/usr/share/applications")).unwrap();
+        fs.remove("/usr/share/applications/app.desktop");
+        let after = fs.metadata(Path::new("/usr/share/applications")).unwrap();
+        assert!(after.modified > before.modified);
+        assert_eq!(
+            fs.read(Path::new("/usr/share/applications/app.desktop"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -1,13 +1,13 @@
 use std::{
     collections::BTreeMap,
     fmt::{self, Display, Formatter},
-    fs,
     path::PathBuf,
 };
 
 use crate::{
-    decoder::{parse_line, Line},
-    DecodeError,
+    decoder::{parse_line_at, Line},
+    span::lines_with_offsets,
+    DecodeError, FileSystem, RealFs,
 };
 
 #[derive(Debug, Clone, Default)]
@@ -44,14 +44,12 @@ pub struct GenericEntry {
 impl GenericEntry {
     pub fn from_str(path: impl Into<PathBuf>, input: &str) -> Result<GenericEntry, DecodeError> {
         #[inline(never)]
-        fn inner<'a>(path: PathBuf, input: &'a str) -> Result<GenericEntry, DecodeError> {
-            let path: PathBuf = path.into();
-
+        fn inner(path: PathBuf, input: &str) -> Result<GenericEntry, DecodeError> {
             let mut groups = Groups::default();
             let mut active_group: Option<(&str, Group)> = None;
 
-            for line in input.lines() {
-                match parse_line(line)? {
+            for (line_number, (offset, line)) in lines_with_offsets(input).enumerate() {
+                match parse_line_at(Some(&path), line_number + 1, offset, line)? {
                     Line::Group(key) => {
                         if let Some((prev_key, prev_group)) =
                             active_group.replace((key, Group::default()))
@@ -81,8 +79,17 @@ impl GenericEntry {
     /// Return an owned [`GenericEntry`]
     #[inline]
     pub fn from_path(path: impl Into<PathBuf>) -> Result<GenericEntry, DecodeError> {
+        Self::from_path_with(&RealFs, path)
+    }
+
+    /// Like [`GenericEntry::from_path`], reading from the given file system.
+    #[inline]
+    pub fn from_path_with(
+        fs: &dyn FileSystem,
+        path: impl Into<PathBuf>,
+    ) -> Result<GenericEntry, DecodeError> {
         let path: PathBuf = path.into();
-        let input = fs::read_to_string(&path)?;
+        let input = fs.read_to_string(&path)?;
         Self::from_str(path, &input)
     }
 
@@ -0,0 +1,361 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{DesktopEntry, DirLayout, InstallKind, Provenance};
+
+/// Entries which likely represent the same application, such as Firefox installed both as a
+/// system package and as a Flatpak.
+#[derive(Debug, Clone)]
+pub struct AppGroup<'a> {
+    /// The entry which the [`GroupPolicy`] prefers.
+    pub representative: &'a DesktopEntry,
+    /// Every entry of the group, including the representative, in their original order.
+    pub members: Vec<&'a DesktopEntry>,
+}
+
+/// The kinds of [`InstallKind`], without their details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SourceKind {
+    System,
+    User,
+    Flatpak,
+    Snap,
+    Nix,
+    AppImage,
+    Distrobox,
+}
+
+/// Decides which entry of an [`AppGroup`] represents it, by how the entries were installed.
+///
+/// ```no_run
+/// use freedesktop_desktop_entry::{
+///     desktop_entries, get_languages_from_env, group_entries, GroupPolicy, SourceKind,
+/// };
+///
+/// let entries = desktop_entries(&get_languages_from_env());
+/// let policy = GroupPolicy::new().prefer([SourceKind::Flatpak, SourceKind::System]);
+///
+/// for group in group_entries(&entries, &policy) {
+///     if group.members.len() > 1 {
+///         println!("{} is installed {} times", group.representative.id(), group.members.len());
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct GroupPolicy {
+    layout: DirLayout,
+    preferred: Vec<SourceKind>,
+}
+
+impl Default for GroupPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            layout: DirLayout::from_env_lossy(),
+            preferred: vec![
+                SourceKind::User,
+                SourceKind::System,
+                SourceKind::Nix,
+                SourceKind::Flatpak,
+                SourceKind::Snap,
+                SourceKind::AppImage,
+                SourceKind::Distrobox,
+            ],
+        }
+    }
+}
+
+impl GroupPolicy {
+    /// Prefers entries installed by the user, then the system, Nix, Flatpak, snap, AppImages
+    /// and distrobox exports, as found in [`DirLayout::from_env_lossy`].
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The layout which the [`Provenance`] of entries is determined from.
+    #[inline]
+    pub fn layout(mut self, layout: DirLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Sources in order of preference. Entries of unlisted sources, or outside of the layout,
+    /// are preferred least.
+    #[inline]
+    pub fn prefer<I: IntoIterator<Item = SourceKind>>(mut self, sources: I) -> Self {
+        self.preferred = sources.into_iter().collect();
+        self
+    }
+
+    fn rank(&self, entry: &DesktopEntry) -> usize {
+        Provenance::of_entry(&self.layout, entry)
+            .and_then(|provenance| {
+                let kind = SourceKind::from(&provenance.kind);
+                self.preferred
+                    .iter()
+                    .position(|preferred| *preferred == kind)
+            })
+            .unwrap_or(self.preferred.len())
+    }
+}
+
+/// Cluster entries which likely represent the same application.
+///
+/// Entries are grouped if they share a `StartupWMClass`, or their ID or `X-Flatpak` is the
+/// same application ID. Otherwise, two of their normalized name, program and icon must match.
+/// Every entry belongs to exactly one group, and groups are in the order of their first member.
+pub fn group_entries<'a>(entries: &'a [DesktopEntry], policy: &GroupPolicy) -> Vec<AppGroup<'a>> {
+    let mut parents = (0..entries.len()).collect::<Vec<_>>();
+    let mut owners = HashMap::<GroupKey, usize>::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        for key in group_keys(entry) {
+            match owners.get(&key) {
+                Some(&owner) => union(&mut parents, owner, index),
+                None => {
+                    owners.insert(key, index);
+                }
+            }
+        }
+    }
+
+    let mut groups = Vec::<Vec<usize>>::new();
+    let mut group_of_root = HashMap::new();
+
+    for index in 0..entries.len() {
+        let root = find(&mut parents, index);
+        let group = *group_of_root.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+
+        groups[group].push(index);
+    }
+
+    groups
+        .into_iter()
+        .map(|members| {
+            let representative = members
+                .iter()
+                .min_by_key(|&&index| policy.rank(&entries[index]))
+                .map(|&index| &entries[index])
+                .unwrap_or(&entries[members[0]]);
+
+            AppGroup {
+                representative,
+                members: members.into_iter().map(|index| &entries[index]).collect(),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum GroupKey {
+    WmClass(String),
+    AppId(String),
+    NameProgram(String, String),
+    NameIcon(String, String),
+    ProgramIcon(String, String),
+}
+
+fn group_keys(entry: &DesktopEntry) -> Vec<GroupKey> {
+    let mut keys = Vec::new();
+
+    if let Some(class) = entry.startup_wm_class().filter(|class| !class.is_empty()) {
+        keys.push(GroupKey::WmClass(class.to_lowercase()));
+    }
+
+    // IDs without a dot are not application IDs, and are too ambiguous on their own.
+    for id in [Some(entry.id()), entry.flatpak()].into_iter().flatten() {
+        if id.contains('.') {
+            keys.push(GroupKey::AppId(id.to_lowercase()));
+        }
+    }
+
+    let name = entry
+        .name(&[] as &[&str])
+        .map(|name| normalize(&name))
+        .filter(|name| !name.is_empty());
+    let program = program_name(entry);
+    let icon = entry
+        .icon()
+        .map(|icon| {
+            // Icons given by path are compared by the name of their file.
+            let path = Path::new(icon);
+            let name = if path.is_absolute() {
+                path.file_stem().and_then(|stem| stem.to_str())
+            } else {
+                Some(icon)
+            };
+
+            name.unwrap_or_default().to_lowercase()
+        })
+        .filter(|icon| !icon.is_empty());
+
+    if let (Some(name), Some(program)) = (&name, &program) {
+        keys.push(GroupKey::NameProgram(name.clone(), program.clone()));
+    }
+
+    if let (Some(name), Some(icon)) = (&name, &icon) {
+        keys.push(GroupKey::NameIcon(name.clone(), icon.clone()));
+    }
+
+    if let (Some(program), Some(icon)) = (program, icon) {
+        keys.push(GroupKey::ProgramIcon(program, icon));
+    }
+
+    keys
+}
+
+/// Lowercase alphanumeric characters of a name, so that `Visual Studio Code` and
+/// `visual-studio-code` are equal.
+#[inline]
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The file name of the program which an entry runs, looking through `env` and the
+/// `flatpak run` wrapper.
+fn program_name(entry: &DesktopEntry) -> Option<String> {
+    let args = entry.parse_exec().ok()?;
+    let mut args = args
+        .iter()
+        .map(String::as_str)
+        .skip_while(|arg| arg.ends_with("/env") || *arg == "env" || arg.contains('='));
+
+    let program = Path::new(args.next()?).file_name()?.to_str()?;
+
+    let program = if program == "flatpak" {
+        match args.find_map(|arg| arg.strip_prefix("--command=")) {
+            Some(command) => Path::new(command).file_name()?.to_str()?,
+            None => entry.flatpak()?.rsplit('.').next()?,
+        }
+    } else {
+        program
+    };
+
+    Some(program.to_lowercase())
+}
+
+fn find(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+
+    index
+}
+
+/// Merge two groups, keeping the lower index as the root so that groups stay in order.
+#[inline]
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    parents[a.max(b)] = a.min(b);
+}
+
+impl From<&InstallKind> for SourceKind {
+    #[inline]
+    fn from(kind: &InstallKind) -> Self {
+        match kind {
+            InstallKind::System => SourceKind::System,
+            InstallKind::User => SourceKind::User,
+            InstallKind::Flatpak { .. } => SourceKind::Flatpak,
+            InstallKind::Snap => SourceKind::Snap,
+            InstallKind::Nix { .. } => SourceKind::Nix,
+            InstallKind::AppImage => SourceKind::AppImage,
+            InstallKind::Distrobox { .. } => SourceKind::Distrobox,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::{group_entries, GroupPolicy, SourceKind};
+    use crate::{DesktopEntry, DirLayout};
+
+    #[test]
+    fn group_duplicate_apps() {
+        let entry =
+            |path: &str, input: &str| DesktopEntry::from_str(path, input, None::<&[&str]>).unwrap();
+
+        let flatpak_firefox =
+            fs::read_to_string("tests_entries/org.mozilla.firefox.desktop").unwrap();
+
+        let entries = [
+            entry(
+                "/usr/share/applications/firefox.desktop",
+                "[Desktop Entry]\nName=Firefox\nExec=/usr/lib/firefox/firefox %u\nIcon=firefox\n",
+            ),
+            entry(
+                "/usr/share/applications/xterm.desktop",
+                "[Desktop Entry]\nName=Terminal\nExec=xterm\nIcon=utilities-terminal\n",
+            ),
+            entry(
+                "/var/lib/flatpak/exports/share/applications/org.mozilla.firefox.desktop",
+                &flatpak_firefox,
+            ),
+            entry(
+                "/usr/share/applications/org.gnome.Console.desktop",
+                "[Desktop Entry]\nName=Terminal\nExec=kgx\nIcon=org.gnome.Console\n",
+            ),
+            entry(
+                "/var/lib/flatpak/exports/share/applications/org.kde.krita.desktop",
+                "[Desktop Entry]\nName=Krita\nExec=/usr/bin/flatpak run --command=krita org.kde.krita\nStartupWMClass=krita\nX-Flatpak=org.kde.krita\n",
+            ),
+            entry(
+                "/usr/share/applications/krita.desktop",
+                "[Desktop Entry]\nName=Krita (Stable)\nExec=env QT_QPA_PLATFORM=xcb krita %F\nStartupWMClass=krita\n",
+            ),
+        ];
+
+        let layout = DirLayout::new()
+            .data_dirs(["/var/lib/flatpak/exports/share", "/usr/share"].map(PathBuf::from));
+
+        let ids = |policy: &GroupPolicy| {
+            group_entries(&entries, policy)
+                .into_iter()
+                .map(|group| {
+                    let members = group
+                        .members
+                        .iter()
+                        .map(|entry| entry.id())
+                        .collect::<Vec<_>>();
+                    (group.representative.id(), members)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let native = GroupPolicy::new().layout(layout.clone());
+        assert_eq!(
+            ids(&native),
+            [
+                ("firefox", vec!["firefox", "org.mozilla.firefox"]),
+                ("xterm", vec!["xterm"]),
+                ("org.gnome.Console", vec!["org.gnome.Console"]),
+                ("krita", vec!["org.kde.krita", "krita"]),
+            ]
+        );
+
+        let flatpak = native.prefer([SourceKind::Flatpak]);
+        assert_eq!(
+            ids(&flatpak)
+                .into_iter()
+                .map(|(representative, _)| representative)
+                .collect::<Vec<_>>(),
+            [
+                "org.mozilla.firefox",
+                "xterm",
+                "org.gnome.Console",
+                "org.kde.krita"
+            ]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    borrow::Borrow,
+    fmt::{self, Display, Formatter},
+    ops::Deref,
+    path::{Component, Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{default_paths, DirLayout};
+
+/// Identifier of a desktop entry, as defined by the [specification]: the path of the file
+/// relative to the `applications` directory it was found in, with `/` replaced by `-`.
+///
+/// Like [`crate::DesktopEntry::appid`], the `.desktop` suffix is not included. So
+/// `/usr/share/applications/kde/konsole.desktop` has the ID `kde-konsole`.
+///
+/// [specification]: https://specifications.freedesktop.org/desktop-entry-spec/latest/file-naming.html
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DesktopFileId(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IdError {
+    #[error("desktop file ID is empty")]
+    Empty,
+    #[error("desktop file ID contains a '/'")]
+    ContainsSlash,
+    #[error("path is not within the given directory")]
+    NotInDirectory,
+    #[error("path is not valid UTF-8")]
+    NonUtf8,
+}
+
+impl DesktopFileId {
+    /// Validate an ID, given without its `.desktop` suffix.
+    pub fn new(id: impl Into<String>) -> Result<Self, IdError> {
+        let id = id.into();
+
+        if id.is_empty() {
+            Err(IdError::Empty)
+        } else if id.contains('/') {
+            Err(IdError::ContainsSlash)
+        } else {
+            Ok(Self(id))
+        }
+    }
+
+    /// Compute the ID of the file at `path`, which was found in the `applications` directory
+    /// `dir`. The extension of the file is not included.
+    pub fn from_path(dir: &Path, path: &Path) -> Result<Self, IdError> {
+        let relative = path
+            .strip_prefix(dir)
+            .map_err(|_| IdError::NotInDirectory)?;
+
+        let mut id = String::with_capacity(relative.as_os_str().len());
+        let mut components = relative.components().peekable();
+
+        while let Some(component) = components.next() {
+            let Component::Normal(name) = component else {
+                return Err(IdError::NotInDirectory);
+            };
+
+            let name = if components.peek().is_none() {
+                Path::new(name).file_stem().unwrap_or(name)
+            } else {
+                name
+            };
+
+            if !id.is_empty() {
+                id.push('-');
+            }
+
+            id.push_str(name.to_str().ok_or(IdError::NonUtf8)?);
+        }
+
+        Self::new(id)
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Name of the file which provides this ID at the top of an `applications` directory.
+    #[inline]
+    pub fn file_name(&self) -> String {
+        [&self.0, ".desktop"].concat()
+    }
+
+    /// Whether the ID is a valid D-Bus well-known name, as the specification requires of
+    /// reverse-DNS IDs and of entries which set `DBusActivatable`.
+    pub fn is_valid_dbus_name(&self) -> bool {
+        let id = self.as_str();
+
+        id.len() <= 255
+            && id.contains('.')
+            && id.split('.').all(|element| {
+                element
+                    .chars()
+                    .next()
+                    .is_some_and(|first| !first.is_ascii_digit())
+                    && element
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            })
+    }
+
+    /// Find the file providing this ID in the given `applications` directories, which are
+    /// searched in order. Each `-` in the ID may stand for a subdirectory.
+    pub fn find_in<I>(&self, application_dirs: I) -> Option<PathBuf>
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        application_dirs
+            .into_iter()
+            .find_map(|dir| find_in_dir(&dir, &self.0))
+    }
+
+    /// Find the file providing this ID in the [`default_paths`].
+    #[inline]
+    pub fn find(&self) -> Option<PathBuf> {
+        self.find_in(default_paths())
+    }
+
+    /// Find the file providing this ID in the [`DirLayout::application_dirs`] of `layout`.
+    #[inline]
+    pub fn find_in_layout(&self, layout: &DirLayout) -> Option<PathBuf> {
+        self.find_in(layout.application_dirs())
+    }
+}
+
+fn find_in_dir(dir: &Path, id: &str) -> Option<PathBuf> {
+    let path = dir.join([id, ".desktop"].concat());
+    if path.is_file() {
+        return Some(path);
+    }
+
+    memchr::memchr_iter(b'-', id.as_bytes()).find_map(|pos| {
+        let subdir = dir.join(&id[..pos]);
+        if subdir.is_dir() {
+            find_in_dir(&subdir, &id[pos + 1..])
+        } else {
+            None
+        }
+    })
+}
+
+impl Deref for DesktopFileId {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for DesktopFileId {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for DesktopFileId {
+    #[inline]
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for DesktopFileId {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<DesktopFileId> for String {
+    #[inline]
+    fn from(id: DesktopFileId) -> Self {
+        id.0
+    }
+}
+
+impl TryFrom<String> for DesktopFileId {
+    type Error = IdError;
+
+    #[inline]
+    fn try_from(id: String) -> Result<Self, IdError> {
+        Self::new(id)
+    }
+}
+
+impl TryFrom<&str> for DesktopFileId {
+    type Error = IdError;
+
+    #[inline]
+    fn try_from(id: &str) -> Result<Self, IdError> {
+        Self::new(id)
+    }
+}
+
+impl PartialEq<str> for DesktopFileId {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for DesktopFileId {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{DesktopFileId, IdError};
+
+    #[test]
+    fn id_from_path() {
+        let dir = Path::new("/usr/share/applications");
+
+        let id = |path: &str| DesktopFileId::from_path(dir, Path::new(path));
+
+        assert_eq!(
+            id("/usr/share/applications/org.gnome.Nautilus.desktop").unwrap(),
+            "org.gnome.Nautilus"
+        );
+        assert_eq!(
+            id("/usr/share/applications/kde/applications/konsole.desktop").unwrap(),
+            "kde-applications-konsole"
+        );
+        assert_eq!(
+            id("/usr/share/other/konsole.desktop"),
+            Err(IdError::NotInDirectory)
+        );
+        assert_eq!(id("/usr/share/applications"), Err(IdError::Empty));
+    }
+
+    #[test]
+    fn dbus_names() {
+        let valid = |id: &str| DesktopFileId::new(id).unwrap().is_valid_dbus_name();
+
+        assert!(valid("org.mozilla.firefox"));
+        assert!(valid(
+            "com.brave.Browser.flextop.brave-cinhimbnkkaeohfgghhklpknlkffjgod-Default"
+        ));
+        assert!(!valid("firefox"));
+        assert!(!valid("org..firefox"));
+        assert!(!valid("org.7zip.App"));
+        assert!(!valid("org.gnome.Nautilus+"));
+    }
+
+    #[test]
+    fn find_in_subdirectories() {
+        let temp = tempfile::tempdir().unwrap();
+        let user = temp.path().join("user");
+        let system = temp.path().join("system");
+        fs::create_dir_all(system.join("kde/tools")).unwrap();
+        fs::create_dir_all(&user).unwrap();
+        fs::write(system.join("kde/tools/konsole.desktop"), "").unwrap();
+        fs::write(system.join("firefox.desktop"), "").unwrap();
+        fs::write(user.join("firefox.desktop"), "").unwrap();
+
+        let dirs = || [user.clone(), system.clone()];
+        let find = |id: &str| DesktopFileId::new(id).unwrap().find_in(dirs());
+
+        assert_eq!(
+            find("kde-tools-konsole"),
+            Some(system.join("kde/tools/konsole.desktop"))
+        );
+        assert_eq!(find("firefox"), Some(user.join("firefox.desktop")));
+        assert_eq!(find("kde-konsole"), None);
+    }
+}
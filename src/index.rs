@@ -0,0 +1,372 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{wine_program_name, AppRegistry, DesktopEntry, WebApp};
+
+/// An index of entries by the properties which [`crate::find_app_by_id`] compares, so that
+/// each lookup is a few hash map accesses instead of scans of every entry.
+///
+/// Lookups return the same entry as [`crate::find_app_by_id`] does for the entries in the
+/// order they were inserted. Entries are identified by their path, and replacing one keeps
+/// its position.
+///
+/// ```no_run
+/// use freedesktop_desktop_entry::{desktop_entries, get_languages_from_env, AppIndex};
+///
+/// let entries = desktop_entries(&get_languages_from_env());
+/// let index = AppIndex::from_entries(&entries);
+///
+/// if let Some(entry) = index.find("firefox") {
+///     println!("{}", entry.path.display());
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AppIndex {
+    slots: Vec<Option<DesktopEntry>>,
+    by_path: HashMap<PathBuf, usize>,
+    /// By the app ID of the web app which the entry launches.
+    web_apps: Keys,
+    wm_classes: Keys,
+    ids: Keys,
+    file_stems: Keys,
+    /// By `X-Flatpak` and `X-Flatpak-RenamedFrom`.
+    flatpak_ids: Keys,
+    names: Keys,
+    execs: Keys,
+    programs: Keys,
+    snap_names: Keys,
+    steam_apps: Keys,
+    wine_programs: Keys,
+}
+
+/// Slots of the entries with a value, by the ASCII lowercase value.
+type Keys = HashMap<String, BTreeSet<usize>>;
+
+impl AppIndex {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_entries(entries: &[DesktopEntry]) -> Self {
+        let mut index = Self::new();
+        index.extend(entries.iter().cloned());
+        index
+    }
+
+    /// An index of the visible entries of a registry, in order of their ID.
+    #[inline]
+    pub fn from_registry(registry: &AppRegistry) -> Self {
+        let mut index = Self::new();
+        index.extend(registry.entries().cloned());
+        index
+    }
+
+    /// Add an entry, or replace the entry with the same path.
+    pub fn insert(&mut self, entry: DesktopEntry) {
+        let slot = match self.by_path.get(&entry.path) {
+            Some(&slot) => {
+                self.take(slot);
+                slot
+            }
+            None => {
+                self.slots.push(None);
+                self.by_path
+                    .insert(entry.path.clone(), self.slots.len() - 1);
+                self.slots.len() - 1
+            }
+        };
+
+        for (field, value) in keys_of(&entry) {
+            self.keys(field).entry(value).or_default().insert(slot);
+        }
+
+        self.slots[slot] = Some(entry);
+    }
+
+    /// Remove the entry at `path`.
+    pub fn remove(&mut self, path: &Path) -> Option<DesktopEntry> {
+        let slot = self.by_path.remove(path)?;
+        self.take(slot)
+    }
+
+    /// Case-insensitive search for the given app ID, with the same cascade as
+    /// [`crate::find_app_by_id`].
+    pub fn find(&self, app_id: &str) -> Option<&DesktopEntry> {
+        let key = app_id.to_ascii_lowercase();
+
+        // The ID matches the desktop file ID or file name, or its last component the file name.
+        let last_component = key.rsplit('.').next().unwrap_or(&key);
+        let by_id = [
+            first(&self.ids, &key),
+            first(&self.file_stems, &key),
+            first(&self.file_stems, last_component),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+
+        let slot = self
+            .find_web_app(app_id)
+            .or_else(|| first(&self.wm_classes, &key))
+            .or(by_id)
+            .or_else(|| first(&self.flatpak_ids, &key))
+            .or_else(|| first(&self.names, &key))
+            .or_else(|| first(&self.execs, &key))
+            .or_else(|| first(&self.programs, &key))
+            .or_else(|| first(&self.snap_names, &key))
+            .or_else(|| first(&self.steam_apps, key.strip_prefix("steam_app_")?))
+            .or_else(|| first(&self.wine_programs, wine_program_name(&key)?))?;
+
+        self.slots[slot].as_ref()
+    }
+
+    /// The earliest entry launching the web app of a window, whose browser and profile match.
+    fn find_web_app(&self, window_id: &str) -> Option<usize> {
+        let window = WebApp::from_window_id(window_id)?;
+
+        self.web_apps
+            .get(&window.app_id)?
+            .iter()
+            .copied()
+            .find(|&slot| {
+                self.slots[slot]
+                    .as_ref()
+                    .and_then(DesktopEntry::web_app)
+                    .is_some_and(|app| window.matches(&app))
+            })
+    }
+
+    #[inline]
+    pub fn get(&self, path: &Path) -> Option<&DesktopEntry> {
+        self.slots[*self.by_path.get(path)?].as_ref()
+    }
+
+    /// Entries in the order they were inserted.
+    #[inline]
+    pub fn entries(&self) -> impl Iterator<Item = &DesktopEntry> {
+        self.slots.iter().flatten()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.by_path.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.by_path.is_empty()
+    }
+
+    /// Take the entry out of a slot, and remove it from the index.
+    fn take(&mut self, slot: usize) -> Option<DesktopEntry> {
+        let entry = self.slots[slot].take()?;
+
+        for (field, value) in keys_of(&entry) {
+            let keys = self.keys(field);
+            if let Some(slots) = keys.get_mut(&value) {
+                slots.remove(&slot);
+                if slots.is_empty() {
+                    keys.remove(&value);
+                }
+            }
+        }
+
+        Some(entry)
+    }
+
+    #[inline]
+    fn keys(&mut self, field: Field) -> &mut Keys {
+        match field {
+            Field::WebApp => &mut self.web_apps,
+            Field::WmClass => &mut self.wm_classes,
+            Field::Id => &mut self.ids,
+            Field::FileStem => &mut self.file_stems,
+            Field::FlatpakId => &mut self.flatpak_ids,
+            Field::Name => &mut self.names,
+            Field::Exec => &mut self.execs,
+            Field::Program => &mut self.programs,
+            Field::SnapName => &mut self.snap_names,
+            Field::SteamApp => &mut self.steam_apps,
+            Field::WineProgram => &mut self.wine_programs,
+        }
+    }
+}
+
+/// The map of [`AppIndex`] which a value is indexed in.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    WebApp,
+    WmClass,
+    Id,
+    FileStem,
+    FlatpakId,
+    Name,
+    Exec,
+    Program,
+    SnapName,
+    SteamApp,
+    WineProgram,
+}
+
+/// Every value which `entry` is indexed by, with its map.
+fn keys_of(entry: &DesktopEntry) -> Vec<(Field, String)> {
+    let file_stem = entry.path.file_stem().and_then(|stem| stem.to_str());
+    let name = entry.name(&[] as &[&str]);
+    let program = entry
+        .exec()
+        .and_then(|exec| exec.split_ascii_whitespace().next());
+    let wine_program = entry.wine_program();
+    let web_app = entry.web_app();
+
+    // Parallel snap instances are only indexed by their instance name.
+    let snap_instance = entry.snap_instance_name();
+    let snap_name = match snap_instance {
+        Some(instance) if instance.contains('_') => None,
+        _ => entry.snap_appname(),
+    };
+
+    let flatpak_ids = entry
+        .flatpak()
+        .into_iter()
+        .chain(entry.flatpak_renamed_from().into_iter().flatten())
+        .map(Some);
+
+    [
+        (
+            Field::WebApp,
+            web_app.as_ref().map(|app| app.app_id.as_str()),
+        ),
+        (Field::WmClass, entry.startup_wm_class()),
+        (Field::Id, Some(entry.id())),
+        (Field::FileStem, file_stem),
+        (Field::Name, name.as_deref()),
+        (Field::Exec, entry.exec()),
+        (Field::Program, program),
+        (Field::SnapName, snap_instance),
+        (Field::SnapName, snap_name),
+        (Field::SteamApp, entry.steam_app_id()),
+        (Field::WineProgram, wine_program.as_deref()),
+    ]
+    .into_iter()
+    .chain(flatpak_ids.map(|id| (Field::FlatpakId, id)))
+    .filter_map(|(field, value)| Some((field, value?.to_ascii_lowercase())))
+    .collect()
+}
+
+/// The earliest slot with a value.
+#[inline]
+fn first(keys: &Keys, key: &str) -> Option<usize> {
+    keys.get(key).and_then(|slots| slots.first().copied())
+}
+
+impl Extend<DesktopEntry> for AppIndex {
+    #[inline]
+    fn extend<I: IntoIterator<Item = DesktopEntry>>(&mut self, entries: I) {
+        for entry in entries {
+            self.insert(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::AppIndex;
+    use crate::{find_app_by_id, DesktopEntry};
+
+    #[test]
+    fn index_matches_cascade() {
+        let mut entries = fs::read_dir("tests_entries")
+            .unwrap()
+            .filter_map(|file| DesktopEntry::from_path(file.unwrap().path(), None::<&[&str]>).ok())
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        entries.push(
+            DesktopEntry::from_str(
+                "/snap/firefox.desktop",
+                "[Desktop Entry]\nName=Firefox Snap\nExec=/snap/bin/firefox\nX-SnapAppName=firefox\n",
+                None::<&[&str]>,
+            )
+            .unwrap(),
+        );
+
+        entries.push(
+            DesktopEntry::from_str(
+                "/home/user/.local/share/applications/dota2.desktop",
+                "[Desktop Entry]\nName=Dota 2\nExec=steam steam://rungameid/570\n",
+                None::<&[&str]>,
+            )
+            .unwrap(),
+        );
+
+        entries.push(
+            DesktopEntry::from_str(
+                "/home/user/.local/share/applications/wine/Notepad++.desktop",
+                "[Desktop Entry]\nName=Notepad++\nExec=env WINEPREFIX=\"/home/user/.wine\" wine C:\\\\\\\\Program\\\\ Files\\\\\\\\Notepad++\\\\\\\\notepad++.exe\n",
+                None::<&[&str]>,
+            )
+            .unwrap(),
+        );
+
+        let mut queries = vec![
+            "steam_app_570",
+            "Steam_App_57",
+            "NOTEPAD++.exe",
+            "notepad++",
+            "missing",
+            "CODE",
+            "FIREFOX",
+            "org.example.nautilus",
+            "krita",
+        ];
+        for entry in &entries {
+            queries.extend(entry.startup_wm_class());
+            queries.push(entry.id());
+            queries.extend(entry.exec());
+            queries.extend(entry.desktop_entry("Name"));
+        }
+
+        let mut index = AppIndex::from_entries(&entries);
+        for query in queries {
+            assert_eq!(
+                index.find(query).map(|entry| &entry.path),
+                find_app_by_id(&entries, unicase::Ascii::new(query)).map(|entry| &entry.path),
+                "{query}"
+            );
+        }
+
+        let firefox = Path::new("tests_entries/org.mozilla.firefox.desktop");
+        assert_eq!(index.find("org.mozilla.firefox").unwrap().path, firefox);
+
+        // Once removed, the last component of the ID matches the file name of the snap.
+        index.remove(firefox);
+        assert_eq!(
+            index.find("org.mozilla.firefox").unwrap().path,
+            Path::new("/snap/firefox.desktop")
+        );
+
+        let entry = |name: &str| {
+            let input = format!("[Desktop Entry]\nName={name}\nExec=firefox-nightly\n");
+            DesktopEntry::from_str(firefox, &input, None::<&[&str]>).unwrap()
+        };
+
+        index.insert(entry("Web"));
+        assert_eq!(index.find("web").unwrap().path, firefox);
+        assert_eq!(index.find("Firefox-Nightly").unwrap().path, firefox);
+
+        // Replacing an entry indexes its new values.
+        index.insert(entry("Browser"));
+        assert_eq!(index.len(), entries.len());
+        assert!(index.find("web").is_none());
+        assert_eq!(index.find("browser").unwrap().path, firefox);
+        assert!(index.find("firefox %u").is_none());
+    }
+}
@@ -3,57 +3,307 @@
 
 use std::{
     collections::{BTreeSet, VecDeque},
-    fs,
-    path::PathBuf,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use crate::DesktopEntry;
+use crate::{
+    scan::ScanEntries, DesktopEntry, DesktopFileId, FileKind, FileSystem, IdError, RealFs,
+};
 
 pub struct Iter {
-    directories_to_walk: VecDeque<PathBuf>,
-    actively_walking: Option<VecDeque<PathBuf>>,
+    fs: Arc<dyn FileSystem>,
+    directories_to_walk: VecDeque<Directory>,
+    actively_walking: Option<Walking>,
     visited: BTreeSet<PathBuf>,
+    skipped: Vec<SkippedDir>,
+    options: WalkOptions,
 }
 
-impl Iter {
+/// A directory waiting to be walked.
+struct Directory {
+    path: PathBuf,
+    /// The directory given by the caller which this one was found in.
+    root: Arc<Path>,
+    /// Known for subdirectories, which are marked as visited before they are queued.
+    canonical: Option<PathBuf>,
+    depth: usize,
+}
+
+/// The directory currently being walked, along with its remaining children.
+struct Walking {
+    entries: VecDeque<(PathBuf, FileKind)>,
+    root: Arc<Path>,
+    canonical: PathBuf,
+    depth: usize,
+}
+
+/// A directory which could not be read while walking, or a link which could not be followed.
+#[derive(Debug)]
+pub struct SkippedDir {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+/// How symbolic links are treated while walking.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Follow links to files and directories alike.
+    #[default]
+    Follow,
+    /// Follow links to files, but do not descend into linked directories.
+    FilesOnly,
+    /// Ignore every link.
+    Ignore,
+}
+
+/// Callback deciding whether a directory should be walked, given its path and depth.
+type DirectoryCallback = Box<dyn FnMut(&Path, usize) -> bool + Send>;
+
+struct WalkOptions {
+    max_depth: Option<usize>,
+    symlinks: SymlinkPolicy,
+    extensions: Vec<OsString>,
+    skip_hidden: bool,
+    on_directory: Option<DirectoryCallback>,
+}
+
+/// Configures the directory walk performed by an [`Iter`].
+///
+/// ```no_run
+/// use freedesktop_desktop_entry::{default_paths, Iter, SymlinkPolicy};
+///
+/// let menus = Iter::builder(default_paths())
+///     .extensions(["directory"])
+///     .max_depth(0)
+///     .symlinks(SymlinkPolicy::FilesOnly)
+///     .build();
+/// ```
+pub struct IterBuilder {
+    fs: Arc<dyn FileSystem>,
+    directories_to_walk: VecDeque<Directory>,
+    options: WalkOptions,
+}
+
+impl IterBuilder {
     /// Directories will be processed in order.
     #[inline]
-    pub fn new<I: Iterator<Item = PathBuf>>(directories_to_walk: I) -> Self {
+    pub fn new<I: IntoIterator<Item = PathBuf>>(directories_to_walk: I) -> Self {
         Self {
-            directories_to_walk: directories_to_walk.collect(),
+            fs: Arc::new(RealFs),
+            directories_to_walk: directories_to_walk
+                .into_iter()
+                .map(|path| Directory {
+                    root: Arc::from(path.as_path()),
+                    path,
+                    canonical: None,
+                    depth: 0,
+                })
+                .collect(),
+            options: WalkOptions {
+                max_depth: None,
+                symlinks: SymlinkPolicy::default(),
+                extensions: vec![OsString::from("desktop")],
+                skip_hidden: false,
+                on_directory: None,
+            },
+        }
+    }
+
+    /// Do not walk directories nested more than `depth` levels below the given directories.
+    /// A depth of 0 only walks the given directories themselves. Unlimited by default.
+    #[inline]
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = Some(depth);
+        self
+    }
+
+    /// How symbolic links should be treated. Defaults to [`SymlinkPolicy::Follow`].
+    #[inline]
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.options.symlinks = policy;
+        self
+    }
+
+    /// File extensions to yield, without the leading dot. Defaults to `desktop`.
+    #[inline]
+    pub fn extensions<I, E>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<OsString>,
+    {
+        self.options.extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Skip files and directories whose name starts with a dot. Disabled by default.
+    #[inline]
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.options.skip_hidden = skip;
+        self
+    }
+
+    /// Called with the path and depth of every directory before it is walked. The directory
+    /// is skipped if the callback returns `false`.
+    #[inline]
+    pub fn on_directory<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&Path, usize) -> bool + Send + 'static,
+    {
+        self.options.on_directory = Some(Box::new(callback));
+        self
+    }
+
+    /// The file system to walk, and to load entries from. Defaults to [`RealFs`].
+    #[inline]
+    pub fn filesystem(mut self, fs: Arc<dyn FileSystem>) -> Self {
+        self.fs = fs;
+        self
+    }
+
+    #[inline]
+    pub fn build(self) -> Iter {
+        Iter {
+            fs: self.fs,
+            directories_to_walk: self.directories_to_walk,
             actively_walking: None,
             visited: BTreeSet::default(),
+            skipped: Vec::new(),
+            options: self.options,
         }
     }
 }
 
+impl Iter {
+    /// Directories will be processed in order.
+    #[inline]
+    pub fn new<I: Iterator<Item = PathBuf>>(directories_to_walk: I) -> Self {
+        IterBuilder::new(directories_to_walk).build()
+    }
+
+    /// Configure the walk over the given directories. See [`IterBuilder`].
+    #[inline]
+    pub fn builder<I: IntoIterator<Item = PathBuf>>(directories_to_walk: I) -> IterBuilder {
+        IterBuilder::new(directories_to_walk)
+    }
+
+    /// Directories which could not be read so far, including those which do not exist.
+    #[inline]
+    pub fn skipped_dirs(&self) -> &[SkippedDir] {
+        &self.skipped
+    }
+
+    /// Consume the iterator, returning the directories which could not be read.
+    #[inline]
+    pub fn into_skipped_dirs(self) -> Vec<SkippedDir> {
+        self.skipped
+    }
+
+    /// The file system being walked.
+    #[inline]
+    pub fn filesystem(&self) -> &Arc<dyn FileSystem> {
+        &self.fs
+    }
+}
+
+impl WalkOptions {
+    #[inline]
+    fn is_hidden(&self, path: &Path) -> bool {
+        self.skip_hidden
+            && path
+                .file_name()
+                .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+    }
+
+    #[inline]
+    fn matches_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| self.extensions.iter().any(|e| e == ext))
+    }
+}
+
 impl Iterator for Iter {
     type Item = PathBuf;
 
+    #[inline]
     fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_root().map(|(path, _root)| path)
+    }
+}
+
+impl Iter {
+    /// Like [`Iterator::next`], but also computes the desktop file ID of the path relative to
+    /// the directory it was found in.
+    #[inline]
+    pub(crate) fn next_with_id(&mut self) -> Option<(PathBuf, Result<DesktopFileId, IdError>)> {
+        let (path, root) = self.next_with_root()?;
+        let id = DesktopFileId::from_path(&root, &path);
+        Some((path, id))
+    }
+
+    /// Like [`Iterator::next`], but also returns the directory given by the caller which the
+    /// path was found in.
+    //
+    // The file type of each child is taken from its directory entry, which on most file systems
+    // requires no additional syscall. Only symlinks are followed with `stat` and `canonicalize`.
+    pub(crate) fn next_with_root(&mut self) -> Option<(PathBuf, Arc<Path>)> {
         'outer: loop {
-            let mut paths = match self.actively_walking.take() {
-                Some(dir) => dir,
+            let mut walking = match self.actively_walking.take() {
+                Some(walking) => walking,
                 None => {
-                    while let Some(path) = self.directories_to_walk.pop_front() {
-                        match fs::read_dir(&path) {
+                    while let Some(directory) = self.directories_to_walk.pop_front() {
+                        if let Some(on_directory) = self.options.on_directory.as_mut() {
+                            if !on_directory(&directory.path, directory.depth) {
+                                continue;
+                            }
+                        }
+
+                        let canonical = match directory.canonical {
+                            Some(canonical) => canonical,
+                            // Directories given by the caller may be reached again through a symlink
+                            None => match self.fs.canonicalize(&directory.path) {
+                                Ok(canonical) if self.visited.insert(canonical.clone()) => {
+                                    canonical
+                                }
+                                Ok(_) => continue,
+                                Err(error) => {
+                                    self.skipped.push(SkippedDir {
+                                        path: directory.path,
+                                        error,
+                                    });
+                                    continue;
+                                }
+                            },
+                        };
+
+                        match self.fs.read_dir(&directory.path) {
                             Ok(dir) => {
-                                self.actively_walking = Some({
-                                    // Pre-sort the walked directories as order of parsing affects appid matches.
-                                    let mut entries = dir
-                                        .filter_map(Result::ok)
-                                        .map(|entry| entry.path())
-                                        .collect::<VecDeque<_>>();
-                                    entries.make_contiguous().sort_unstable();
-                                    entries
+                                // Pre-sort the walked directories as order of parsing affects appid matches.
+                                let mut entries = VecDeque::from(dir);
+                                entries
+                                    .make_contiguous()
+                                    .sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+                                self.actively_walking = Some(Walking {
+                                    entries,
+                                    root: directory.root,
+                                    canonical,
+                                    depth: directory.depth,
                                 });
 
                                 continue 'outer;
                             }
 
-                            // Skip directories_to_walk which could not be read or that were
-                            // already visited
-                            _ => continue,
+                            // Skip directories_to_walk which could not be read
+                            Err(error) => {
+                                self.skipped.push(SkippedDir {
+                                    path: directory.path,
+                                    error,
+                                });
+                                continue;
+                            }
                         }
                     }
 
@@ -61,30 +311,70 @@ impl Iterator for Iter {
                 }
             };
 
-            'inner: while let Some(mut path) = paths.pop_front() {
-                if !path.exists() {
+            'inner: while let Some((path, file_type)) = walking.entries.pop_front() {
+                if self.options.is_hidden(&path) {
                     continue 'inner;
                 }
 
-                if path.is_dir() {
-                    path = match path.canonicalize() {
-                        Ok(canonicalized) => canonicalized,
-                        Err(_) => continue 'inner,
-                    };
-                }
+                let target_type = if file_type == FileKind::Symlink {
+                    if self.options.symlinks == SymlinkPolicy::Ignore {
+                        continue 'inner;
+                    }
 
-                if let Ok(metadata) = path.metadata() {
-                    if metadata.is_dir() {
-                        // Skip visited directories to mitigate against file system loops
-                        if self.visited.insert(path.clone()) {
-                            self.directories_to_walk.push_front(path);
+                    match self.fs.metadata(&path) {
+                        Ok(metadata) => metadata.kind,
+                        // Broken links
+                        Err(error) => {
+                            self.skipped.push(SkippedDir { path, error });
+                            continue 'inner;
                         }
-                    } else if metadata.is_file()
-                        && path.extension().is_some_and(|ext| ext == "desktop")
+                    }
+                } else {
+                    file_type
+                };
+
+                if target_type == FileKind::Dir {
+                    if self
+                        .options
+                        .max_depth
+                        .is_some_and(|max| walking.depth >= max)
                     {
-                        self.actively_walking = Some(paths);
-                        return Some(path);
+                        continue 'inner;
+                    }
+
+                    let canonical = if file_type == FileKind::Symlink {
+                        if self.options.symlinks == SymlinkPolicy::FilesOnly {
+                            continue 'inner;
+                        }
+
+                        match self.fs.canonicalize(&path) {
+                            Ok(canonical) => canonical,
+                            Err(error) => {
+                                self.skipped.push(SkippedDir { path, error });
+                                continue 'inner;
+                            }
+                        }
+                    } else {
+                        // A real directory within a canonical path is itself canonical.
+                        match path.file_name() {
+                            Some(name) => walking.canonical.join(name),
+                            None => continue 'inner,
+                        }
+                    };
+
+                    // Skip visited directories to mitigate against file system loops
+                    if self.visited.insert(canonical.clone()) {
+                        self.directories_to_walk.push_front(Directory {
+                            path,
+                            root: walking.root.clone(),
+                            canonical: Some(canonical),
+                            depth: walking.depth + 1,
+                        });
                     }
+                } else if target_type == FileKind::File && self.options.matches_extension(&path) {
+                    let root = walking.root.clone();
+                    self.actively_walking = Some(walking);
+                    return Some((path, root));
                 }
             }
         }
@@ -100,16 +390,39 @@ impl Iter {
     where
         L: AsRef<str>,
     {
-        self.map(move |path| DesktopEntry::from_path(path, locales_filter))
-            .filter_map(|e| e.ok())
+        let fs = self.fs.clone();
+        self.with_ids().filter_map(move |(id, path)| {
+            let mut entry = DesktopEntry::from_path_with(&*fs, path, locales_filter).ok()?;
+            entry.appid = id.into();
+            Some(entry)
+        })
+    }
+
+    /// Yield the desktop file ID of every path along with the path itself. Files whose ID
+    /// is invalid, such as those with non-UTF-8 names, are skipped.
+    #[inline]
+    pub fn with_ids(mut self) -> impl Iterator<Item = (DesktopFileId, PathBuf)> {
+        std::iter::from_fn(move || self.next_with_id())
+            .filter_map(|(path, id)| Some((id.ok()?, path)))
+    }
+
+    /// Like [`Iter::entries`], but entries which fail to load are reported instead of
+    /// being dropped.
+    #[inline]
+    pub fn scan_entries<L>(self, locales_filter: Option<&[L]>) -> ScanEntries<'_, L>
+    where
+        L: AsRef<str>,
+    {
+        ScanEntries::new(self, locales_filter)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{fs, os::unix};
+    use std::{fs, os::unix, path::Path, sync::Arc};
 
-    use super::{DesktopEntry, Iter};
+    use super::{DesktopEntry, Iter, SymlinkPolicy};
+    use crate::MemoryFs;
 
     #[test]
     fn iter_yields_all_entries() {
@@ -146,11 +459,13 @@ mod tests {
             fs::write(file, DesktopEntry::from_appid(name.to_string()).to_string()).unwrap();
         }
 
-        let mut iter = Iter::new(
-            fs::read_dir(root)
-                .unwrap()
-                .map(|entry| entry.unwrap().path()),
-        );
+        let mut roots = fs::read_dir(root)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        roots.sort_unstable();
+
+        let mut iter = Iter::new(roots.into_iter());
         for (expected, actual) in all_files.iter().zip(&mut iter) {
             assert_eq!(*expected, actual);
         }
@@ -193,4 +508,103 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn iter_ids_relative_to_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("applications");
+        let nested = root.join("kde/applications");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join("org.gnome.Nautilus.desktop"), "").unwrap();
+        fs::write(nested.join("konsole.desktop"), "").unwrap();
+
+        let ids = Iter::new([root].into_iter())
+            .with_ids()
+            .map(|(id, _)| id.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(ids, ["org.gnome.Nautilus", "kde-applications-konsole"]);
+    }
+
+    #[test]
+    fn iter_walks_memory_fs() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.add_file("/data/applications/a.desktop", "[Desktop Entry]\nName=A\n");
+        fs.add_file(
+            "/data/applications/kde/b.desktop",
+            "[Desktop Entry]\nName=B\n",
+        );
+        fs.add_symlink("/data/applications/loop", "..");
+        fs.add_symlink("/data/applications/broken.desktop", "missing.desktop");
+
+        let entries = Iter::builder(["/data/applications".into(), "/missing".into()])
+            .filesystem(fs)
+            .build()
+            .entries(None::<&[&str]>)
+            .map(|entry| entry.appid)
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries, ["a", "kde-b"]);
+    }
+
+    #[test]
+    fn iter_builder_options() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let sub = root.join("sub");
+        fs::create_dir(&sub).unwrap();
+        unix::fs::symlink(&sub, root.join("link")).unwrap();
+
+        for file in [
+            "a.desktop",
+            ".hidden.desktop",
+            "menu.directory",
+            "sub/b.desktop",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let walk = |iter: Iter| {
+            iter.map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+
+        let shallow = Iter::builder([root.to_path_buf()])
+            .extensions(["desktop", "directory"])
+            .skip_hidden(true)
+            .max_depth(0)
+            .build();
+        assert_eq!(
+            walk(shallow),
+            [Path::new("a.desktop"), Path::new("menu.directory")]
+        );
+
+        let without_links = Iter::builder([root.to_path_buf()])
+            .symlinks(SymlinkPolicy::Ignore)
+            .build();
+        assert_eq!(
+            walk(without_links),
+            [
+                Path::new(".hidden.desktop"),
+                Path::new("a.desktop"),
+                Path::new("sub/b.desktop")
+            ]
+        );
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let pruned = Iter::builder([root.to_path_buf()])
+            .on_directory(move |path, depth| {
+                sender.send((path.to_path_buf(), depth)).unwrap();
+                depth == 0
+            })
+            .build();
+        assert_eq!(
+            walk(pruned),
+            [Path::new(".hidden.desktop"), Path::new("a.desktop")]
+        );
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [(root.to_path_buf(), 0), (root.join("link"), 1)]
+        );
+    }
 }
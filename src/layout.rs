@@ -0,0 +1,351 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+/// Locations of the XDG base directories, as defined by the [XDG Base Directory
+/// Specification], which desktop entries, autostart entries and MIME associations are
+/// discovered in.
+///
+/// ```no_run
+/// use freedesktop_desktop_entry::{DirLayout, Iter};
+///
+/// let layout = DirLayout::from_env()
+///     .unwrap()
+///     .sysroot("/var/lib/machines/fedora");
+///
+/// for path in Iter::new(layout.application_dirs().into_iter()) {
+///     println!("{}", path.display());
+/// }
+/// ```
+///
+/// [XDG Base Directory Specification]: https://specifications.freedesktop.org/basedir-spec/latest/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirLayout {
+    data_home: Option<PathBuf>,
+    data_dirs: Vec<PathBuf>,
+    config_home: Option<PathBuf>,
+    config_dirs: Vec<PathBuf>,
+    cache_home: Option<PathBuf>,
+    sysroot: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LayoutError {
+    #[error("{0} cannot be determined, because HOME is not set to an absolute path")]
+    NoHome(&'static str),
+}
+
+impl DirLayout {
+    /// An empty layout, to be filled in explicitly.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the layout from the `XDG_*` variables, falling back to the defaults of the
+    /// specification. Relative paths are ignored, as the specification requires.
+    ///
+    /// Returns an error if the data or config home cannot be determined. Use
+    /// [`DirLayout::from_env_lossy`] to omit them instead.
+    #[inline]
+    pub fn from_env() -> Result<Self, LayoutError> {
+        Self::from_env_lossy().require_home()
+    }
+
+    /// Like [`DirLayout::from_env`], but the directories within the home directory are
+    /// omitted if it cannot be determined.
+    #[inline]
+    pub fn from_env_lossy() -> Self {
+        Self::from_vars(|name| env::var_os(name))
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<OsString>) -> Self {
+        let home = var("HOME").map(PathBuf::from).filter(|p| p.is_absolute());
+
+        let home_dir = |name: &str, default: &str| {
+            var(name)
+                .map(PathBuf::from)
+                .filter(|p| p.is_absolute())
+                .or_else(|| home.as_ref().map(|home| home.join(default)))
+        };
+
+        let dirs = |name: &str, default: &[&str]| {
+            let dirs = var(name)
+                .map(|dirs| absolute_paths(&dirs))
+                .unwrap_or_default();
+
+            if dirs.is_empty() {
+                default.iter().map(PathBuf::from).collect()
+            } else {
+                dirs
+            }
+        };
+
+        Self {
+            data_home: home_dir("XDG_DATA_HOME", ".local/share"),
+            data_dirs: dirs("XDG_DATA_DIRS", &["/usr/local/share", "/usr/share"]),
+            config_home: home_dir("XDG_CONFIG_HOME", ".config"),
+            config_dirs: dirs("XDG_CONFIG_DIRS", &["/etc/xdg"]),
+            cache_home: home_dir("XDG_CACHE_HOME", ".cache"),
+            sysroot: None,
+        }
+    }
+
+    fn require_home(self) -> Result<Self, LayoutError> {
+        if self.data_home.is_none() {
+            Err(LayoutError::NoHome("XDG_DATA_HOME"))
+        } else if self.config_home.is_none() {
+            Err(LayoutError::NoHome("XDG_CONFIG_HOME"))
+        } else {
+            Ok(self)
+        }
+    }
+
+    #[inline]
+    pub fn data_home(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.data_home = Some(dir.into()).filter(|dir| dir.is_absolute());
+        self
+    }
+
+    /// System data directories, in order of priority.
+    #[inline]
+    pub fn data_dirs<I: IntoIterator<Item = PathBuf>>(mut self, dirs: I) -> Self {
+        self.data_dirs = dirs.into_iter().filter(|dir| dir.is_absolute()).collect();
+        self
+    }
+
+    #[inline]
+    pub fn config_home(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config_home = Some(dir.into()).filter(|dir| dir.is_absolute());
+        self
+    }
+
+    /// System config directories, in order of priority.
+    #[inline]
+    pub fn config_dirs<I: IntoIterator<Item = PathBuf>>(mut self, dirs: I) -> Self {
+        self.config_dirs = dirs.into_iter().filter(|dir| dir.is_absolute()).collect();
+        self
+    }
+
+    #[inline]
+    pub fn cache_home(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_home = Some(dir.into()).filter(|dir| dir.is_absolute());
+        self
+    }
+
+    /// Prefix every directory with `sysroot`, such as the root of a container image.
+    #[inline]
+    pub fn sysroot(mut self, sysroot: impl Into<PathBuf>) -> Self {
+        self.sysroot = Some(sysroot.into());
+        self
+    }
+
+    /// The data home, within the sysroot if one is set.
+    #[inline]
+    pub fn get_data_home(&self) -> Option<PathBuf> {
+        self.data_home.as_deref().map(|dir| self.prefixed(dir))
+    }
+
+    /// The config home, within the sysroot if one is set.
+    #[inline]
+    pub fn get_config_home(&self) -> Option<PathBuf> {
+        self.config_home.as_deref().map(|dir| self.prefixed(dir))
+    }
+
+    /// The cache home, within the sysroot if one is set.
+    #[inline]
+    pub fn get_cache_home(&self) -> Option<PathBuf> {
+        self.cache_home.as_deref().map(|dir| self.prefixed(dir))
+    }
+
+    /// Data directories, starting with the data home, in order of priority.
+    pub fn data_search_dirs(&self) -> Vec<PathBuf> {
+        self.data_home
+            .iter()
+            .chain(&self.data_dirs)
+            .map(|dir| self.prefixed(dir))
+            .collect()
+    }
+
+    /// Config directories, starting with the config home, in order of priority.
+    pub fn config_search_dirs(&self) -> Vec<PathBuf> {
+        self.config_home
+            .iter()
+            .chain(&self.config_dirs)
+            .map(|dir| self.prefixed(dir))
+            .collect()
+    }
+
+    /// Directories which desktop entries are searched for in, in order of priority.
+    #[inline]
+    pub fn application_dirs(&self) -> Vec<PathBuf> {
+        self.data_search_dirs()
+            .into_iter()
+            .map(|dir| dir.join("applications"))
+            .collect()
+    }
+
+    /// Directories which autostart entries are searched for in, in order of priority.
+    #[inline]
+    pub fn autostart_dirs(&self) -> Vec<PathBuf> {
+        self.config_search_dirs()
+            .into_iter()
+            .map(|dir| dir.join("autostart"))
+            .collect()
+    }
+
+    /// Files which MIME associations are read from, in order of priority. Desktop-specific
+    /// lists, such as `gnome-mimeapps.list`, are included for each of `desktops`.
+    pub fn mimeapps_lists<S: AsRef<str>>(&self, desktops: &[S]) -> Vec<PathBuf> {
+        let names = desktops
+            .iter()
+            .map(|desktop| format!("{}-mimeapps.list", desktop.as_ref().to_ascii_lowercase()))
+            .chain(Some(String::from("mimeapps.list")))
+            .collect::<Vec<_>>();
+
+        // The user's and system's config, then the user's and system's data
+        self.config_search_dirs()
+            .into_iter()
+            .chain(self.application_dirs())
+            .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+            .collect()
+    }
+
+    /// The data home as given, without the sysroot.
+    #[inline]
+    pub(crate) fn data_home_dir(&self) -> Option<&Path> {
+        self.data_home.as_deref()
+    }
+
+    #[inline]
+    pub(crate) fn sysroot_dir(&self) -> Option<&Path> {
+        self.sysroot.as_deref()
+    }
+
+    /// Remove the sysroot from a path within it.
+    #[inline]
+    pub(crate) fn unprefixed(&self, path: &Path) -> PathBuf {
+        match self
+            .sysroot
+            .as_ref()
+            .map(|sysroot| path.strip_prefix(sysroot))
+        {
+            Some(Ok(path)) => Path::new("/").join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    #[inline]
+    fn prefixed(&self, dir: &Path) -> PathBuf {
+        match self.sysroot.as_ref() {
+            Some(sysroot) => sysroot.join(dir.strip_prefix("/").unwrap_or(dir)),
+            None => dir.to_path_buf(),
+        }
+    }
+}
+
+/// Absolute paths of a `:` separated list. Relative paths are invalid and ignored.
+#[inline]
+fn absolute_paths(dirs: &OsString) -> Vec<PathBuf> {
+    env::split_paths(dirs)
+        .filter(|dir| dir.is_absolute())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, ffi::OsString, path::PathBuf};
+
+    use super::{DirLayout, LayoutError};
+
+    fn layout(vars: &[(&str, &str)]) -> DirLayout {
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), OsString::from(v)))
+            .collect::<HashMap<_, _>>();
+
+        DirLayout::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn layout_from_vars() {
+        let layout = layout(&[
+            ("HOME", "/home/user"),
+            ("XDG_DATA_HOME", "relative/share"),
+            ("XDG_DATA_DIRS", "/opt/share:share:/usr/share"),
+        ]);
+
+        assert_eq!(
+            layout.application_dirs(),
+            [
+                PathBuf::from("/home/user/.local/share/applications"),
+                PathBuf::from("/opt/share/applications"),
+                PathBuf::from("/usr/share/applications"),
+            ]
+        );
+        assert_eq!(
+            layout.autostart_dirs(),
+            [
+                PathBuf::from("/home/user/.config/autostart"),
+                PathBuf::from("/etc/xdg/autostart"),
+            ]
+        );
+
+        assert_eq!(
+            layout.get_cache_home(),
+            Some(PathBuf::from("/home/user/.cache"))
+        );
+
+        let prefixed = layout.sysroot("/sysroot");
+        assert_eq!(
+            prefixed.application_dirs()[2],
+            PathBuf::from("/sysroot/usr/share/applications")
+        );
+        assert_eq!(
+            prefixed.get_cache_home(),
+            Some(PathBuf::from("/sysroot/home/user/.cache"))
+        );
+    }
+
+    #[test]
+    fn layout_without_home() {
+        let layout = layout(&[("HOME", "home"), ("XDG_CONFIG_HOME", "/config")]);
+
+        assert_eq!(
+            layout.application_dirs(),
+            [
+                PathBuf::from("/usr/local/share/applications"),
+                PathBuf::from("/usr/share/applications"),
+            ]
+        );
+        assert_eq!(
+            layout.mimeapps_lists(&["GNOME"])[..4],
+            [
+                PathBuf::from("/config/gnome-mimeapps.list"),
+                PathBuf::from("/config/mimeapps.list"),
+                PathBuf::from("/etc/xdg/gnome-mimeapps.list"),
+                PathBuf::from("/etc/xdg/mimeapps.list"),
+            ]
+        );
+
+        let explicit = DirLayout::new()
+            .data_home("relative")
+            .data_dirs([PathBuf::from("/usr/share")]);
+        assert_eq!(
+            explicit.application_dirs(),
+            [PathBuf::from("/usr/share/applications")]
+        );
+
+        assert_eq!(
+            layout.require_home(),
+            Err(LayoutError::NoHome("XDG_DATA_HOME"))
+        );
+    }
+}
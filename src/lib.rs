@@ -1,17 +1,62 @@
 // Copyright 2021 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "cache")]
+mod cache;
 mod decoder;
 mod exec;
+mod filesystem;
 mod generic_entry;
+mod group;
+mod id;
+mod index;
 mod iter;
+mod layout;
+mod matcher;
+mod package;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod process;
+mod provenance;
+mod registry;
+mod sandbox;
+mod scan;
+mod span;
 #[cfg(test)]
 mod tests;
-
-pub use self::iter::Iter;
-pub use decoder::{parse_line, DecodeError};
-pub use exec::ExecError;
+mod visibility;
+#[cfg(feature = "watch")]
+mod watch;
+mod webapp;
+
+pub use self::iter::{Iter, IterBuilder, SkippedDir, SymlinkPolicy};
+#[cfg(feature = "cache")]
+pub use cache::{Cache, CachedEntries};
+pub use decoder::{parse_line, DecodeError, Recovery, Warning};
+pub use exec::{find_executable, ExecError, ResolveError};
+pub use filesystem::{FileKind, FileMetadata, FileSystem, MemoryFs, RealFs};
 pub use generic_entry::GenericEntry;
+pub use group::{group_entries, AppGroup, GroupPolicy, SourceKind};
+pub use id::{DesktopFileId, IdError};
+pub use index::AppIndex;
+pub use layout::{DirLayout, LayoutError};
+pub use matcher::{AppMatch, AppMatcher, MatchKind, MatchReason, MatchStrategy};
+pub use package::{Package, PackageManager, PackageResolver};
+#[cfg(feature = "parallel")]
+pub use parallel::{par_desktop_entries, par_desktop_entries_in};
+pub use process::{ProcessEvidence, ProcessMatch, ProcessResolver};
+#[allow(deprecated)]
+pub use provenance::PathSource;
+pub use provenance::{InstallKind, Provenance};
+pub use registry::{AppRegistry, Candidate, CandidateState};
+pub use sandbox::FlatpakSandbox;
+pub use scan::{
+    scan_desktop_entries, scan_desktop_entries_in, ScanEntries, ScanError, ScanErrorKind,
+    ScanReport,
+};
+pub use span::Span;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt::{self, Display, Formatter};
@@ -19,12 +64,25 @@ use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 pub use unicase;
 use unicase::Ascii;
-use xdg::BaseDirectories;
+pub use visibility::{HiddenReason, ShowContext};
+#[cfg(feature = "watch")]
+pub use watch::{WatchEvent, Watcher};
+pub use webapp::{find_web_app, Browser, WebApp};
 
-/// Read all desktop entries on disk into a Vec, with only the given locales retained.
+/// Read all desktop entries on disk into a Vec, with only the given locales retained. Their IDs
+/// are relative to the data directory they were found in, as with [`Iter::entries`].
+///
+/// Entries which fail to load are silently dropped. Use [`scan_desktop_entries`] to find out why.
+/// Every file is returned, including overridden and hidden entries. Use [`AppRegistry`] to only
+/// keep the entries which are visible according to the specification.
 pub fn desktop_entries(locales: &[String]) -> Vec<DesktopEntry> {
-    Iter::new(default_paths())
-        .filter_map(|p| DesktopEntry::from_path(p, Some(&locales)).ok())
+    desktop_entries_in(&DirLayout::from_env_lossy(), locales)
+}
+
+/// Like [`desktop_entries`], reading the [`DirLayout::application_dirs`] of `layout`.
+pub fn desktop_entries_in(layout: &DirLayout, locales: &[String]) -> Vec<DesktopEntry> {
+    Iter::new(layout.application_dirs().into_iter())
+        .entries(Some(locales))
         .collect::<Vec<_>>()
 }
 
@@ -32,19 +90,30 @@ pub fn desktop_entries(locales: &[String]) -> Vec<DesktopEntry> {
 ///
 /// Requires using the `unicase` crate for its `Ascii` case support.
 ///
-/// Searches by name if an ID match could not be found.
+/// Windows of [web apps](WebApp) match the entry which launches them first. Searches by name
+/// if an ID match could not be found. Use [`AppMatcher`] to rank every
+/// match and find out why it matched, or [`AppIndex`] to look up many app IDs.
 pub fn find_app_by_id<'a>(
     entries: &'a [DesktopEntry],
     app_id: Ascii<&str>,
 ) -> Option<&'a DesktopEntry> {
     // NOTE: Use `cargo run --example find_appid {{wm_app_id}}` to check if the match works.
 
-    // Prefer desktop entries whose startup wm class is a perfect match.
-    let match_by_wm_class = entries.iter().find(|entry| entry.matches_wm_class(app_id));
+    // Web app windows are named after the browser, app and profile of the entry. The window ID
+    // is parsed once, and entries are only examined if it belongs to a web app.
+    let match_by_web_app = find_web_app(entries, &app_id);
 
-    match_by_wm_class
+    match_by_web_app
+        // Prefer desktop entries whose startup wm class is a perfect match.
+        .or_else(|| entries.iter().find(|entry| entry.matches_wm_class(app_id)))
         // If no suitable wm class was found, search by entry file name.
         .or_else(|| entries.iter().find(|entry| entry.matches_id(app_id)))
+        // Then by the ID of the Flatpak, which is shared by all of its entries.
+        .or_else(|| {
+            entries
+                .iter()
+                .find(|entry| entry.matches_flatpak_id(app_id))
+        })
         // Otherwise by name specified in the desktop entry.
         .or_else(|| entries.iter().find(|entry| entry.matches_name(app_id)))
         // Or match by the exact exec command
@@ -69,6 +138,24 @@ pub fn find_app_by_id<'a>(
                 .iter()
                 .find(|entry| entry.matches_snap_appname(app_id))
         })
+        // Match games launched by Steam, whose windows are named `steam_app_<id>`
+        .or_else(|| entries.iter().find(|entry| entry.matches_steam_app(app_id)))
+        // Match Windows programs run by Wine, whose windows are named `<program>.exe`
+        .or_else(|| {
+            entries
+                .iter()
+                .find(|entry| entry.matches_wine_program(app_id))
+        })
+}
+
+/// Programs which launch Windows programs, as the first command of an `Exec`.
+const WINE_LAUNCHERS: [&str; 4] = ["wine", "wine64", "wine-stable", "proton"];
+
+/// The name of a Windows program without `.exe`, as Wine names its windows.
+#[inline]
+pub(crate) fn wine_program_name(class: &str) -> Option<&str> {
+    let (name, extension) = class.rsplit_once('.')?;
+    (extension.eq_ignore_ascii_case("exe") && !name.is_empty()).then_some(name)
 }
 
 #[derive(Debug, Clone, Default)]
@@ -155,7 +242,7 @@ impl Ord for DesktopEntry {
 
 impl PartialOrd for DesktopEntry {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.path.cmp(&other.path))
+        Some(self.cmp(other))
     }
 }
 
@@ -197,11 +284,36 @@ impl DesktopEntry {
             .is_some_and(|wm_class| wm_class == id)
     }
 
-    /// Match snap apps by snap app name.
+    /// Match snap apps by snap app name, or by the instance name of the snap. Parallel
+    /// instances, named `<snap>_<key>`, are only matched by their instance name.
     #[inline]
     pub fn matches_snap_appname(&self, name: Ascii<&str>) -> bool {
-        self.snap_appname()
-            .is_some_and(|snap_name| snap_name == name)
+        match self.snap_instance_name() {
+            Some(instance) if instance.contains('_') => name == instance,
+            Some(instance) if name == instance => true,
+            _ => self
+                .snap_appname()
+                .is_some_and(|snap_name| snap_name == name),
+        }
+    }
+
+    /// Match a game launched by Steam, or Proton, by a window class of `steam_app_<id>`.
+    #[inline]
+    pub fn matches_steam_app(&self, class: Ascii<&str>) -> bool {
+        let prefix = "steam_app_".len();
+        class.len() > prefix
+            && class.is_char_boundary(prefix)
+            && class[..prefix].eq_ignore_ascii_case("steam_app_")
+            && self.steam_app_id() == Some(&class[prefix..])
+    }
+
+    /// Match a Windows program run by Wine by the name of its executable, such as `foo.exe`.
+    #[inline]
+    pub fn matches_wine_program(&self, name: Ascii<&str>) -> bool {
+        wine_program_name(&name).is_some_and(|name| {
+            self.wine_program()
+                .is_some_and(|program| program.eq_ignore_ascii_case(name))
+        })
     }
 
     /// Match entry by desktop entry file name
@@ -215,10 +327,21 @@ impl DesktopEntry {
                 .is_some_and(|name| {
                     name == id
                         // Or match by last part of app ID
-                        || id.split('.').rev().next().is_some_and(|id| id == name)
+                        || id.split('.').next_back().is_some_and(|id| id == name)
                 })
     }
 
+    /// Match by the ID of the Flatpak which the entry belongs to, or one it was renamed from.
+    /// Every entry of a Flatpak shares its ID, so this should only be used if a match by the
+    /// desktop file ID failed.
+    #[inline]
+    pub fn matches_flatpak_id(&self, id: Ascii<&str>) -> bool {
+        self.flatpak().is_some_and(|flatpak| flatpak == id)
+            || self
+                .flatpak_renamed_from()
+                .is_some_and(|ids| ids.into_iter().any(|old| old == id))
+    }
+
     // Match by name specified in desktop entry, which should only be used if a match by ID failed.
     #[inline]
     pub fn matches_name(&self, name: Ascii<&str>) -> bool {
@@ -375,16 +498,92 @@ impl DesktopEntry {
         self.desktop_entry("X-Flatpak")
     }
 
+    /// The ID of the Steam app which the entry launches with a `steam://rungameid/<id>` or
+    /// `steam://run/<id>` URL.
+    pub fn steam_app_id(&self) -> Option<&str> {
+        let exec = self.exec()?;
+        ["steam://rungameid/", "steam://run/"]
+            .iter()
+            .find_map(|scheme| {
+                let id = &exec[exec.find(scheme)? + scheme.len()..];
+                let end = id.find(|c: char| !c.is_ascii_digit()).unwrap_or(id.len());
+                Some(&id[..end])
+            })
+            .filter(|id| !id.is_empty())
+    }
+
+    /// The name of the Windows program, without its extension, which the entry runs with
+    /// Wine. Entries which Wine creates start a shortcut, which is named after the program.
+    ///
+    /// The command must be one of the Wine launchers, possibly after `env` and its variables.
+    pub fn wine_program(&self) -> Option<String> {
+        let exec = self.exec()?;
+
+        // The launcher, after the variables which `env` sets.
+        let launcher = exec
+            .split_ascii_whitespace()
+            .map(|arg| arg.trim_matches(['"', '\'']))
+            .find(|arg| *arg != "env" && !arg.contains('='))?;
+        let launcher = launcher.rsplit('/').next().unwrap_or(launcher);
+
+        if !WINE_LAUNCHERS.contains(&launcher) {
+            return None;
+        }
+
+        // The last `.exe` or `.lnk` file, such as `C:\\Games\\foo.exe` or `Foo\ Bar.lnk`.
+        let lower = exec.to_ascii_lowercase();
+        let end = [".exe", ".lnk"]
+            .iter()
+            .filter_map(|extension| lower.rfind(extension))
+            .max()?;
+
+        let bytes = exec.as_bytes();
+        let mut start = end;
+        while start > 0 {
+            let c = bytes[start - 1];
+            let escaped_space = c == b'\\' && bytes.get(start) == Some(&b' ');
+            let unescaped_space = c == b' ' && (start < 2 || bytes[start - 2] != b'\\');
+            if matches!(c, b'/' | b'"' | b'\'' | b'=')
+                || unescaped_space
+                || (c == b'\\' && !escaped_space)
+            {
+                break;
+            }
+            start -= 1;
+        }
+
+        let name = exec[start..end].replace("\\ ", " ");
+        (!name.is_empty()).then_some(name)
+    }
+
     #[inline]
     pub fn prefers_non_default_gpu(&self) -> bool {
         self.desktop_entry_bool("PrefersNonDefaultGPU")
     }
 
+    /// Desktop file IDs which the Flatpak of this entry had before it was renamed, without
+    /// their `.desktop` suffix.
+    #[inline]
+    pub fn flatpak_renamed_from(&self) -> Option<Vec<&str>> {
+        self.desktop_entry("X-Flatpak-RenamedFrom").map(|ids| {
+            ids.split(';')
+                .map(|id| id.strip_suffix(".desktop").unwrap_or(id))
+                .filter(|id| !id.is_empty())
+                .collect()
+        })
+    }
+
     #[inline]
     pub fn snap_appname(&self) -> Option<&str> {
         self.desktop_entry("X-SnapAppName")
     }
 
+    /// The name of the snap instance, which is `<snap>_<key>` for parallel installs.
+    #[inline]
+    pub fn snap_instance_name(&self) -> Option<&str> {
+        self.desktop_entry("X-SnapInstanceName")
+    }
+
     #[inline]
     pub fn startup_notify(&self) -> bool {
         self.desktop_entry_bool("StartupNotify")
@@ -494,7 +693,7 @@ impl DesktopEntry {
 
     #[inline]
     fn desktop_entry_bool(&self, key: &str) -> bool {
-        self.desktop_entry(key).map_or(false, |v| v == "true")
+        self.desktop_entry(key) == Some("true")
     }
 
     #[inline(never)]
@@ -615,71 +814,18 @@ impl Default for IconSource {
     }
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-pub enum PathSource {
-    Local,
-    LocalDesktop,
-    LocalFlatpak,
-    LocalNix,
-    Nix,
-    System,
-    SystemLocal,
-    SystemFlatpak,
-    SystemSnap,
-    Other(String),
-}
-
-impl PathSource {
-    /// Attempts to determine the PathSource for a given Path.
-    /// Note that this is a best-effort guesting function, and its results should be treated as
-    /// such (e.g.: non-canonical).
-    pub fn guess_from(path: &Path) -> PathSource {
-        let base_dirs = BaseDirectories::new();
-        let data_home = base_dirs.get_data_home().unwrap();
-        let mut nix_state = base_dirs.get_state_home().unwrap();
-        nix_state.push("nix");
-
-        if path.starts_with("/usr/share") {
-            PathSource::System
-        } else if path.starts_with("/usr/local/share") {
-            PathSource::SystemLocal
-        } else if path.starts_with("/var/lib/flatpak") {
-            PathSource::SystemFlatpak
-        } else if path.starts_with("/var/lib/snapd") {
-            PathSource::SystemSnap
-        } else if path.starts_with("/nix/var/nix/profiles/default")
-            || path.starts_with("/nix/store")
-            || path.starts_with("/run/current-system/sw")
-        {
-            PathSource::Nix
-        } else if path.to_string_lossy().contains("/flatpak/") {
-            PathSource::LocalFlatpak
-        } else if path.starts_with(data_home.as_path()) {
-            PathSource::Local
-        } else if path.starts_with("/nix/var/nix/profiles/per-user")
-            || path.to_string_lossy().contains(".nix")
-            || path.starts_with(nix_state.as_path())
-        {
-            PathSource::LocalNix
-        } else {
-            PathSource::Other(String::from("unknown"))
-        }
-    }
-}
-
 /// Returns the default paths in which desktop entries should be searched for based on the current
 /// environment.
 /// Paths are sorted by priority.
 ///
-/// Panics in case determining the current home directory fails.
+/// The data home is omitted if the home directory cannot be determined. Use [`DirLayout`] to
+/// handle that case, or to search other directories.
+///
+/// Within a Flatpak sandbox, these are the directories of the sandbox. Use
+/// [`FlatpakSandbox::host_layout`] to find the applications of the host.
 #[cold]
 pub fn default_paths() -> impl Iterator<Item = PathBuf> {
-    let base_dirs = BaseDirectories::new();
-    let mut data_dirs: Vec<PathBuf> = vec![];
-    data_dirs.push(base_dirs.get_data_home().unwrap());
-    data_dirs.append(&mut base_dirs.get_data_dirs());
-
-    data_dirs.into_iter().map(|d| d.join("applications"))
+    DirLayout::from_env_lossy().application_dirs().into_iter()
 }
 
 #[cfg(feature = "gettext")]
@@ -709,13 +855,18 @@ pub fn get_languages_from_env() -> Vec<String> {
     l
 }
 
+/// Names of the current desktops from `XDG_CURRENT_DESKTOP`, in order of priority.
+///
+/// Names are case-sensitive, as in `OnlyShowIn` and `NotShowIn`. Unity falls back to GNOME.
 pub fn current_desktop() -> Option<Vec<String>> {
     std::env::var("XDG_CURRENT_DESKTOP").ok().map(|x| {
-        let x = x.to_ascii_lowercase();
-        if x == "unity" {
-            vec!["gnome".to_string()]
+        if x == "Unity" {
+            vec!["Unity".to_string(), "GNOME".to_string()]
         } else {
-            x.split(':').map(|e| e.to_string()).collect()
+            x.split(':')
+                .filter(|e| !e.is_empty())
+                .map(|e| e.to_string())
+                .collect()
         }
     })
 }
@@ -0,0 +1,367 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+};
+
+use unicase::Ascii;
+
+use crate::{DesktopEntry, WebApp};
+
+/// Suffixes which are ignored when comparing normalized names.
+const SUFFIXES: [&str; 3] = [".desktop", ".exe", "-bin"];
+
+/// A property of an entry which an app ID, such as a Wayland `app_id` or an X11 `WM_CLASS`, is
+/// compared with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MatchStrategy {
+    /// The [web app](crate::WebApp) which the entry launches.
+    WebApp,
+    /// `StartupWMClass`
+    WmClass,
+    /// The desktop file ID, or the file name of the entry.
+    Id,
+    /// `X-Flatpak`, or `X-Flatpak-RenamedFrom`, which every entry of a Flatpak shares.
+    FlatpakId,
+    /// The untranslated `Name`.
+    Name,
+    /// The whole `Exec` command.
+    Exec,
+    /// The program of the `Exec` command.
+    ExecProgram,
+    /// `X-SnapAppName`, or `X-SnapInstanceName`.
+    SnapAppName,
+    /// The Steam app of a window class `steam_app_<id>`.
+    SteamApp,
+    /// The Wine program of a window class `<program>.exe`.
+    WineProgram,
+}
+
+/// How closely a value matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MatchKind {
+    /// Equal after [normalization](AppMatcher): ignoring case, paths, reverse-DNS prefixes,
+    /// and the suffixes `.desktop`, `.exe` and `-bin`.
+    Normalized,
+    /// Equal ignoring ASCII case.
+    IgnoreCase,
+    Exact,
+}
+
+/// Why an entry matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MatchReason {
+    pub strategy: MatchStrategy,
+    pub kind: MatchKind,
+}
+
+/// An entry which matched, with a score that is higher for better matches.
+#[derive(Debug, Clone)]
+pub struct AppMatch<'a> {
+    pub entry: &'a DesktopEntry,
+    pub score: u32,
+    pub reason: MatchReason,
+}
+
+/// Finds every entry which matches an app ID, ranked by the order of the strategies and how
+/// closely they matched. A match of an earlier strategy always ranks higher.
+///
+/// ```no_run
+/// use freedesktop_desktop_entry::{desktop_entries, get_languages_from_env, AppMatcher};
+///
+/// let entries = desktop_entries(&get_languages_from_env());
+///
+/// for found in AppMatcher::new().find_all(&entries, "firefox-bin") {
+///     println!("{} ({}): {}", found.entry.id(), found.score, found.reason);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AppMatcher {
+    strategies: Vec<MatchStrategy>,
+}
+
+impl Default for AppMatcher {
+    /// Web apps, then the order of [`crate::find_app_by_id`].
+    #[inline]
+    fn default() -> Self {
+        Self {
+            strategies: vec![
+                MatchStrategy::WebApp,
+                MatchStrategy::WmClass,
+                MatchStrategy::Id,
+                MatchStrategy::FlatpakId,
+                MatchStrategy::Name,
+                MatchStrategy::Exec,
+                MatchStrategy::ExecProgram,
+                MatchStrategy::SnapAppName,
+                MatchStrategy::SteamApp,
+                MatchStrategy::WineProgram,
+            ],
+        }
+    }
+}
+
+impl AppMatcher {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Strategies to try, in order of priority. Unlisted strategies are disabled.
+    #[inline]
+    pub fn strategies<I: IntoIterator<Item = MatchStrategy>>(mut self, strategies: I) -> Self {
+        self.strategies = strategies.into_iter().collect();
+        self
+    }
+
+    #[inline]
+    pub fn disable(mut self, strategy: MatchStrategy) -> Self {
+        self.strategies.retain(|s| *s != strategy);
+        self
+    }
+
+    /// Every entry which matches `app_id`, best first. Entries which match equally well keep
+    /// their order.
+    pub fn find_all<'a>(&self, entries: &'a [DesktopEntry], app_id: &str) -> Vec<AppMatch<'a>> {
+        let normalized = normalize(app_id);
+        let window = WebApp::from_window_id(app_id);
+
+        let mut matches = entries
+            .iter()
+            .filter_map(|entry| self.match_entry(entry, app_id, &normalized, window.as_ref()))
+            .collect::<Vec<_>>();
+
+        matches.sort_by_key(|found| std::cmp::Reverse(found.score));
+        matches
+    }
+
+    /// The entry which matches `app_id` best.
+    #[inline]
+    pub fn find<'a>(&self, entries: &'a [DesktopEntry], app_id: &str) -> Option<AppMatch<'a>> {
+        self.find_all(entries, app_id).into_iter().next()
+    }
+
+    fn match_entry<'a>(
+        &self,
+        entry: &'a DesktopEntry,
+        app_id: &str,
+        normalized: &str,
+        window: Option<&WebApp>,
+    ) -> Option<AppMatch<'a>> {
+        self.strategies
+            .iter()
+            .enumerate()
+            .filter_map(|(priority, &strategy)| {
+                let kind = match strategy {
+                    // The window ID is parsed once, rather than for every entry.
+                    MatchStrategy::WebApp => window.is_some_and(|window| {
+                        entry.web_app().is_some_and(|app| window.matches(&app))
+                    }),
+                    MatchStrategy::SteamApp => entry.matches_steam_app(Ascii::new(app_id)),
+                    MatchStrategy::WineProgram => entry.matches_wine_program(Ascii::new(app_id)),
+                    _ => false,
+                }
+                .then_some(MatchKind::Exact)
+                .or_else(|| {
+                    values(entry, strategy)
+                        .into_iter()
+                        .filter_map(|value| compare(value, app_id, normalized))
+                        .max()
+                })?;
+
+                // Each strategy outranks every later one, whatever the kind of match.
+                let rank = (self.strategies.len() - priority) as u32;
+                Some(AppMatch {
+                    entry,
+                    score: rank * 10 + kind as u32,
+                    reason: MatchReason { strategy, kind },
+                })
+            })
+            .max_by_key(|found| found.score)
+    }
+}
+
+/// The values of an entry which a strategy compares.
+fn values(entry: &DesktopEntry, strategy: MatchStrategy) -> Vec<&str> {
+    match strategy {
+        MatchStrategy::WebApp | MatchStrategy::SteamApp | MatchStrategy::WineProgram => Vec::new(),
+        MatchStrategy::WmClass => entry.startup_wm_class().into_iter().collect(),
+        MatchStrategy::Id => {
+            let file_stem = entry.path.file_stem().and_then(|stem| stem.to_str());
+            [Some(entry.id()), file_stem]
+                .into_iter()
+                .flatten()
+                .collect()
+        }
+        MatchStrategy::FlatpakId => entry
+            .flatpak()
+            .into_iter()
+            .chain(entry.flatpak_renamed_from().into_iter().flatten())
+            .collect(),
+        MatchStrategy::Name => entry.desktop_entry("Name").into_iter().collect(),
+        MatchStrategy::Exec => entry.exec().into_iter().collect(),
+        MatchStrategy::ExecProgram => entry
+            .exec()
+            .and_then(|exec| exec.split_ascii_whitespace().next())
+            .map(|program| program.trim_matches('"'))
+            .into_iter()
+            .collect(),
+        MatchStrategy::SnapAppName => match entry.snap_instance_name() {
+            // Parallel instances are only matched by their instance name.
+            Some(instance) if instance.contains('_') => vec![instance],
+            instance => instance.into_iter().chain(entry.snap_appname()).collect(),
+        },
+    }
+}
+
+#[inline]
+fn compare(value: &str, app_id: &str, normalized: &str) -> Option<MatchKind> {
+    if value.is_empty() {
+        None
+    } else if value == app_id {
+        Some(MatchKind::Exact)
+    } else if value.eq_ignore_ascii_case(app_id) {
+        Some(MatchKind::IgnoreCase)
+    } else if !normalized.is_empty() && normalize(value) == normalized {
+        Some(MatchKind::Normalized)
+    } else {
+        None
+    }
+}
+
+/// Lowercase the file name of `value`, keep the last component of a reverse-DNS name, and
+/// remove common suffixes, so that `/usr/bin/Firefox-bin`, `firefox.exe` and
+/// `org.mozilla.firefox` all become `firefox`.
+fn normalize(value: &str) -> String {
+    let value = value.trim().trim_matches('"');
+    let mut name = Path::new(value)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(value)
+        .to_lowercase();
+
+    strip_suffixes(&mut name);
+
+    if let Some((_, last)) = name.rsplit_once('.') {
+        name = last.to_owned();
+    }
+
+    strip_suffixes(&mut name);
+
+    name
+}
+
+#[inline]
+fn strip_suffixes(name: &mut String) {
+    for suffix in SUFFIXES {
+        if let Some(stripped) = name.strip_suffix(suffix) {
+            name.truncate(stripped.len());
+        }
+    }
+}
+
+impl Display for MatchStrategy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MatchStrategy::WebApp => "web app",
+            MatchStrategy::WmClass => "StartupWMClass",
+            MatchStrategy::Id => "desktop file ID",
+            MatchStrategy::FlatpakId => "Flatpak ID",
+            MatchStrategy::Name => "Name",
+            MatchStrategy::Exec => "Exec",
+            MatchStrategy::ExecProgram => "program of Exec",
+            MatchStrategy::SnapAppName => "X-SnapAppName",
+            MatchStrategy::SteamApp => "Steam app",
+            MatchStrategy::WineProgram => "Wine program",
+        })
+    }
+}
+
+impl Display for MatchReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            MatchKind::Exact => write!(f, "{} matched exactly", self.strategy),
+            MatchKind::IgnoreCase => write!(f, "{} matched ignoring case", self.strategy),
+            MatchKind::Normalized => write!(f, "{} matched after normalization", self.strategy),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, AppMatcher, MatchKind, MatchReason, MatchStrategy};
+    use crate::DesktopEntry;
+
+    #[test]
+    fn normalize_app_ids() {
+        assert_eq!(normalize("/usr/lib/firefox/Firefox-bin"), "firefox");
+        assert_eq!(normalize("org.mozilla.firefox.desktop"), "firefox");
+        assert_eq!(normalize("Steam.exe"), "steam");
+        assert_eq!(normalize("code"), "code");
+    }
+
+    #[test]
+    fn rank_matches() {
+        let entry = |path: &str, keys: &str| {
+            let input = format!("[Desktop Entry]\nName=App\n{keys}");
+            DesktopEntry::from_str(path, &input, None::<&[&str]>).unwrap()
+        };
+
+        let entries = [
+            entry("/a/org.mozilla.firefox.desktop", "Exec=firefox %u\n"),
+            entry(
+                "/a/code.desktop",
+                "Exec=/usr/share/code/code\nStartupWMClass=Code\n",
+            ),
+            entry(
+                "/a/firefox-esr.desktop",
+                "Exec=firefox-esr\nStartupWMClass=Firefox-esr\n",
+            ),
+        ];
+
+        let reasons = |matcher: &AppMatcher, app_id: &str| {
+            matcher
+                .find_all(&entries, app_id)
+                .into_iter()
+                .map(|found| (found.entry.id(), found.reason))
+                .collect::<Vec<_>>()
+        };
+
+        let reason = |strategy, kind| MatchReason { strategy, kind };
+
+        assert_eq!(
+            reasons(&AppMatcher::new(), "code"),
+            [(
+                "code",
+                reason(MatchStrategy::WmClass, MatchKind::IgnoreCase)
+            )]
+        );
+        assert_eq!(
+            reasons(&AppMatcher::new(), "firefox-bin"),
+            [(
+                "org.mozilla.firefox",
+                reason(MatchStrategy::Id, MatchKind::Normalized)
+            ),]
+        );
+
+        let by_program = AppMatcher::new()
+            .strategies([MatchStrategy::ExecProgram, MatchStrategy::Id])
+            .disable(MatchStrategy::Id);
+        assert_eq!(
+            reasons(&by_program, "Firefox"),
+            [(
+                "org.mozilla.firefox",
+                reason(MatchStrategy::ExecProgram, MatchKind::IgnoreCase)
+            )]
+        );
+
+        let all = AppMatcher::new().find_all(&entries, "firefox-esr");
+        assert_eq!(all.len(), 1);
+        assert_eq!(
+            all[0].reason.to_string(),
+            "StartupWMClass matched ignoring case"
+        );
+    }
+}
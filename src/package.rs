@@ -0,0 +1,485 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use crate::{provenance::flatpak_installation, DesktopEntry, FileKind, FileSystem, RealFs};
+
+/// The package which installed a desktop entry.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Package {
+    /// Name of the package, which the package manager uninstalls it by: a Debian or Arch
+    /// package name, a Flatpak application ID, or a snap instance name.
+    pub name: String,
+    pub version: Option<String>,
+    pub manager: PackageManager,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PackageManager {
+    Dpkg,
+    Pacman,
+    Flatpak,
+    Snap,
+}
+
+/// Finds the package which installed a desktop entry, from the databases of dpkg and pacman,
+/// the deployments of Flatpak, and the layout of snaps in `/snap/<name>/current`.
+///
+/// The databases of dpkg and pacman are read the first time they are needed.
+///
+/// ```no_run
+/// use freedesktop_desktop_entry::{DesktopEntry, PackageResolver};
+///
+/// let resolver = PackageResolver::new();
+/// let entry = DesktopEntry::from_path(
+///     "/usr/share/applications/firefox.desktop",
+///     None::<&[&str]>,
+/// )
+/// .unwrap();
+///
+/// if let Some(package) = resolver.resolve_entry(&entry) {
+///     println!("installed by {} from {}", package.manager, package.name);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct PackageResolver {
+    fs: Arc<dyn FileSystem>,
+    sysroot: Option<PathBuf>,
+    dpkg: OnceLock<HashMap<PathBuf, Package>>,
+    pacman: OnceLock<HashMap<PathBuf, Package>>,
+}
+
+impl Default for PackageResolver {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            fs: Arc::new(RealFs),
+            sysroot: None,
+            dpkg: OnceLock::new(),
+            pacman: OnceLock::new(),
+        }
+    }
+}
+
+impl PackageResolver {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The file system which package databases are read from. Defaults to [`RealFs`].
+    #[inline]
+    pub fn filesystem(mut self, fs: Arc<dyn FileSystem>) -> Self {
+        self.fs = fs;
+        self
+    }
+
+    /// Read the package databases within `sysroot`, such as the root of a container image.
+    /// Paths given to the resolver are expected to be within it as well.
+    #[inline]
+    pub fn sysroot(mut self, sysroot: impl Into<PathBuf>) -> Self {
+        self.sysroot = Some(sysroot.into());
+        self
+    }
+
+    #[inline]
+    pub fn resolve_entry(&self, entry: &DesktopEntry) -> Option<Package> {
+        self.resolve(&entry.path)
+    }
+
+    /// The package which installed the desktop entry at `path`.
+    pub fn resolve(&self, path: &Path) -> Option<Package> {
+        let host_path = self.unprefixed(path);
+
+        self.flatpak(path)
+            .or_else(|| self.snap(&host_path))
+            .or_else(|| self.dpkg_index().get(&host_path).cloned())
+            .or_else(|| self.pacman_index().get(&host_path).cloned())
+    }
+
+    /// Applications exported by Flatpak, in `<installation>/exports/share/applications`. The
+    /// file name of every exported entry starts with the ID of its application.
+    fn flatpak(&self, path: &Path) -> Option<Package> {
+        let data_dir = path.parent()?.parent()?;
+        let installation = flatpak_installation(data_dir)?;
+        let stem = path.file_stem()?.to_str()?;
+
+        let deployment = self
+            .fs
+            .read_dir(&installation.join("app"))
+            .ok()?
+            .into_iter()
+            .filter_map(|(app, _)| app.file_name()?.to_str().map(str::to_owned))
+            .filter(|id| {
+                stem.strip_prefix(id.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            })
+            .max_by_key(String::len)?;
+
+        let active = installation
+            .join("app")
+            .join(&deployment)
+            .join("current/active");
+        if !self.is_file(&active.join("metadata")) {
+            return None;
+        }
+
+        let version = ["metainfo", "appdata"].iter().find_map(|kind| {
+            let metainfo = active.join(format!("files/share/{kind}/{deployment}.{kind}.xml"));
+            release_version(&self.fs.read_to_string(&metainfo).ok()?)
+        });
+
+        Some(Package {
+            name: deployment,
+            version,
+            manager: PackageManager::Flatpak,
+        })
+    }
+
+    /// Snaps whose entries snapd installed into `/var/lib/snapd/desktop/applications`, named
+    /// `<instance>_<app>.desktop`, or entries within `/snap/<instance>`.
+    fn snap(&self, host_path: &Path) -> Option<Package> {
+        let instance =
+            if let Ok(file) = host_path.strip_prefix("/var/lib/snapd/desktop/applications") {
+                let stem = file.to_str()?.strip_suffix(".desktop")?;
+                // Parallel instances `<name>_<key>` are written as `<name>+<key>`.
+                stem.split_once('_')?.0.replace('+', "_")
+            } else {
+                let name = host_path.strip_prefix("/snap").ok()?.iter().next()?;
+                name.to_str()?.to_owned()
+            };
+
+        let snap_yaml = self.prefixed(
+            Path::new("/snap")
+                .join(&instance)
+                .join("current/meta/snap.yaml"),
+        );
+        let yaml = self.fs.read_to_string(&snap_yaml).ok()?;
+
+        let version = yaml.lines().find_map(|line| {
+            let version = line.strip_prefix("version:")?.trim();
+            Some(version.trim_matches(['"', '\'']).to_owned())
+        });
+
+        Some(Package {
+            name: instance,
+            version,
+            manager: PackageManager::Snap,
+        })
+    }
+
+    fn dpkg_index(&self) -> &HashMap<PathBuf, Package> {
+        self.dpkg.get_or_init(|| {
+            let info = self.prefixed("/var/lib/dpkg/info");
+            let versions = self
+                .fs
+                .read_to_string(&self.prefixed("/var/lib/dpkg/status"))
+                .map(|status| dpkg_versions(&status))
+                .unwrap_or_default();
+
+            let mut index = HashMap::new();
+
+            for (list, _) in self.fs.read_dir(&info).into_iter().flatten() {
+                let Some(package) = list
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_suffix(".list"))
+                else {
+                    continue;
+                };
+
+                let Ok(files) = self.fs.read_to_string(&list) else {
+                    continue;
+                };
+
+                // Multi-arch packages are listed as `<name>:<arch>.list`.
+                let name = package.split_once(':').map_or(package, |(name, _)| name);
+                let version = versions.get(package).or_else(|| versions.get(name));
+
+                for file in desktop_files(&files) {
+                    index.insert(
+                        PathBuf::from(file),
+                        Package {
+                            name: name.to_owned(),
+                            version: version.cloned(),
+                            manager: PackageManager::Dpkg,
+                        },
+                    );
+                }
+            }
+
+            index
+        })
+    }
+
+    fn pacman_index(&self) -> &HashMap<PathBuf, Package> {
+        self.pacman.get_or_init(|| {
+            let local = self.prefixed("/var/lib/pacman/local");
+            let mut index = HashMap::new();
+
+            for (dir, _) in self.fs.read_dir(&local).into_iter().flatten() {
+                let Ok(desc) = self.fs.read_to_string(&dir.join("desc")) else {
+                    continue;
+                };
+
+                let Some(name) = pacman_field(&desc, "%NAME%") else {
+                    continue;
+                };
+
+                let Ok(files) = self.fs.read_to_string(&dir.join("files")) else {
+                    continue;
+                };
+
+                let version = pacman_field(&desc, "%VERSION%");
+
+                // Files are listed relative to the root, after `%FILES%`.
+                let files = files.split_once("%FILES%").map_or("", |(_, files)| files);
+
+                for file in desktop_files(files) {
+                    index.insert(
+                        Path::new("/").join(file),
+                        Package {
+                            name: name.to_owned(),
+                            version: version.map(str::to_owned),
+                            manager: PackageManager::Pacman,
+                        },
+                    );
+                }
+            }
+
+            index
+        })
+    }
+
+    #[inline]
+    fn is_file(&self, path: &Path) -> bool {
+        self.fs
+            .metadata(path)
+            .is_ok_and(|metadata| metadata.kind == FileKind::File)
+    }
+
+    #[inline]
+    fn prefixed(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        match self.sysroot.as_ref() {
+            Some(sysroot) => sysroot.join(path.strip_prefix("/").unwrap_or(path)),
+            None => path.to_path_buf(),
+        }
+    }
+
+    #[inline]
+    fn unprefixed(&self, path: &Path) -> PathBuf {
+        match self
+            .sysroot
+            .as_ref()
+            .map(|sysroot| path.strip_prefix(sysroot))
+        {
+            Some(Ok(path)) => Path::new("/").join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+/// Desktop entries in a list of files, one per line.
+#[inline]
+fn desktop_files(files: &str) -> impl Iterator<Item = &str> {
+    files
+        .lines()
+        .map(str::trim)
+        .filter(|file| file.ends_with(".desktop"))
+}
+
+/// Versions of the installed packages in the status database of dpkg, by package name and by
+/// `<name>:<arch>`.
+fn dpkg_versions(status: &str) -> HashMap<String, String> {
+    let mut versions = HashMap::new();
+
+    for stanza in status.split("\n\n") {
+        let field = |name: &str| {
+            stanza
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .map(str::trim)
+        };
+
+        let (Some(package), Some(version)) = (field("Package"), field("Version")) else {
+            continue;
+        };
+
+        if !field("Status").is_some_and(|status| status.ends_with(" installed")) {
+            continue;
+        }
+
+        if let Some(arch) = field("Architecture") {
+            versions.insert(format!("{package}:{arch}"), version.to_owned());
+        }
+
+        versions.insert(package.to_owned(), version.to_owned());
+    }
+
+    versions
+}
+
+/// The value of a field in a `desc` file of pacman, which is on the line after its name.
+#[inline]
+fn pacman_field<'a>(desc: &'a str, name: &str) -> Option<&'a str> {
+    let mut lines = desc.lines();
+    lines.find(|line| *line == name)?;
+    lines
+        .next()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Version of the latest release in AppStream metadata, which lists releases newest first.
+fn release_version(metainfo: &str) -> Option<String> {
+    let release = &metainfo[metainfo.find("<release ")?..];
+    let release = &release[..release.find('>')?];
+    let version = release.split_once("version=")?.1;
+
+    let quote = version.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let version = &version[1..];
+    Some(version[..version.find(quote)?].to_owned())
+}
+
+impl Display for PackageManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PackageManager::Dpkg => "dpkg",
+            PackageManager::Pacman => "pacman",
+            PackageManager::Flatpak => "flatpak",
+            PackageManager::Snap => "snap",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, sync::Arc};
+
+    use super::{Package, PackageManager, PackageResolver};
+    use crate::MemoryFs;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn resolve_packages() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        write(
+            &root.join("var/lib/dpkg/info/firefox:amd64.list"),
+            "/.\n/usr/bin/firefox\n/usr/share/applications/firefox.desktop\n",
+        );
+        write(
+            &root.join("var/lib/dpkg/status"),
+            "Package: vim\nStatus: install ok installed\nVersion: 9.1\n\n\
+             Package: firefox\nStatus: install ok installed\nArchitecture: amd64\n\
+             Version: 128.0-1\n",
+        );
+
+        write(
+            &root.join("var/lib/pacman/local/gimp-2.10.38-1/desc"),
+            "%NAME%\ngimp\n\n%VERSION%\n2.10.38-1\n",
+        );
+        write(
+            &root.join("var/lib/pacman/local/gimp-2.10.38-1/files"),
+            "%FILES%\nusr/\nusr/share/applications/gimp.desktop\n",
+        );
+
+        let deploy = root.join("var/lib/flatpak/app/org.gnome.Maps/current/active");
+        write(
+            &deploy.join("metadata"),
+            "[Application]\nname=org.gnome.Maps\n",
+        );
+        write(
+            &deploy.join("files/share/metainfo/org.gnome.Maps.metainfo.xml"),
+            "<releases>\n<release version=\"47.1\" date=\"2024-10-20\"/>\n<release version=\"47.0\"/>",
+        );
+
+        write(
+            &root.join("snap/firefox_esr/current/meta/snap.yaml"),
+            "name: firefox\nversion: '115.9'\n",
+        );
+
+        let resolver = PackageResolver::new().sysroot(root);
+        let resolve = |path: &str| resolver.resolve(&root.join(path));
+
+        assert_eq!(
+            resolve("usr/share/applications/firefox.desktop"),
+            Some(Package {
+                name: "firefox".into(),
+                version: Some("128.0-1".into()),
+                manager: PackageManager::Dpkg,
+            })
+        );
+        assert_eq!(
+            resolve("usr/share/applications/gimp.desktop"),
+            Some(Package {
+                name: "gimp".into(),
+                version: Some("2.10.38-1".into()),
+                manager: PackageManager::Pacman,
+            })
+        );
+        assert_eq!(
+            resolve("var/lib/flatpak/exports/share/applications/org.gnome.Maps.Tool.desktop"),
+            Some(Package {
+                name: "org.gnome.Maps".into(),
+                version: Some("47.1".into()),
+                manager: PackageManager::Flatpak,
+            })
+        );
+        assert_eq!(
+            resolve("var/lib/snapd/desktop/applications/firefox+esr_firefox.desktop"),
+            Some(Package {
+                name: "firefox_esr".into(),
+                version: Some("115.9".into()),
+                manager: PackageManager::Snap,
+            })
+        );
+        assert_eq!(resolve("usr/share/applications/vim.desktop"), None);
+        assert_eq!(
+            resolve("var/lib/flatpak/exports/share/applications/org.gnome.MapsX.desktop"),
+            None
+        );
+    }
+
+    #[test]
+    fn resolve_packages_from_filesystem() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.add_file(
+            "/var/lib/dpkg/info/firefox.list",
+            "/usr/share/applications/firefox.desktop\n",
+        );
+        fs.add_file(
+            "/var/lib/dpkg/status",
+            "Package: firefox\nStatus: install ok installed\nVersion: 128.0-1\n",
+        );
+        fs.add_file(
+            "/var/lib/flatpak/app/org.gnome.Maps/current/active/metadata",
+            "[Application]\nname=org.gnome.Maps\n",
+        );
+
+        let resolver = PackageResolver::new().filesystem(fs);
+        let resolve = |path: &str| resolver.resolve(Path::new(path)).map(|p| p.name);
+
+        assert_eq!(
+            resolve("/usr/share/applications/firefox.desktop").as_deref(),
+            Some("firefox")
+        );
+        assert_eq!(
+            resolve("/var/lib/flatpak/exports/share/applications/org.gnome.Maps.desktop")
+                .as_deref(),
+            Some("org.gnome.Maps")
+        );
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use rayon::prelude::*;
+
+use crate::{scan, DesktopEntry, DirLayout, Iter, ScanError, ScanReport};
+
+impl Iter {
+    /// Like [`Iter::entries`], but files are read and decoded on the rayon thread pool.
+    ///
+    /// Directories are still walked sequentially, and entries are returned in the same order
+    /// as [`Iter::entries`] would yield them.
+    pub fn par_entries<L>(self, locales_filter: Option<&[L]>) -> Vec<DesktopEntry>
+    where
+        L: AsRef<str> + Sync,
+    {
+        let fs = self.filesystem().clone();
+        self.with_ids()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .filter_map(|(id, path)| {
+                let mut entry = DesktopEntry::from_path_with(&*fs, path, locales_filter).ok()?;
+                entry.appid = id.into();
+                Some(entry)
+            })
+            .collect()
+    }
+
+    /// Like [`Iter::scan_entries`], but files are read and decoded on the rayon thread pool.
+    ///
+    /// Entries and errors are each returned in the order in which they were found.
+    pub fn par_scan<L>(mut self, locales_filter: Option<&[L]>) -> ScanReport
+    where
+        L: AsRef<str> + Sync,
+    {
+        let paths = std::iter::from_fn(|| self.next_with_id()).collect::<Vec<_>>();
+        let fs = &**self.filesystem();
+
+        let results = paths
+            .into_par_iter()
+            .map(|(path, id)| {
+                scan::load(fs, &path, id, locales_filter).map_err(|kind| ScanError { path, kind })
+            })
+            .collect::<Vec<_>>();
+
+        let mut report = ScanReport {
+            skipped_dirs: self.into_skipped_dirs(),
+            ..ScanReport::default()
+        };
+
+        for result in results {
+            match result {
+                Ok(entry) => report.entries.push(entry),
+                Err(error) => report.errors.push(error),
+            }
+        }
+
+        report
+    }
+}
+
+/// Like [`crate::desktop_entries`], but files are read and decoded in parallel.
+#[inline]
+pub fn par_desktop_entries(locales: &[String]) -> Vec<DesktopEntry> {
+    par_desktop_entries_in(&DirLayout::from_env_lossy(), locales)
+}
+
+/// Like [`par_desktop_entries`], reading the [`DirLayout::application_dirs`] of `layout`.
+pub fn par_desktop_entries_in(layout: &DirLayout, locales: &[String]) -> Vec<DesktopEntry> {
+    Iter::new(layout.application_dirs().into_iter()).par_entries(Some(locales))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{DesktopEntry, Iter};
+
+    #[test]
+    fn par_entries_keep_walk_order() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir(root.join("nested")).unwrap();
+
+        for name in ["b", "a", "nested/c", "d", "nested/e"] {
+            let appid = name.replace('/', "-");
+            fs::write(
+                root.join(name).with_extension("desktop"),
+                DesktopEntry::from_appid(appid).to_string(),
+            )
+            .unwrap();
+        }
+        fs::write(root.join("broken.desktop"), "Name=Broken\n").unwrap();
+
+        let sequential = Iter::new([root.to_path_buf()].into_iter())
+            .entries(None::<&[&str]>)
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>();
+        let parallel = Iter::new([root.to_path_buf()].into_iter())
+            .par_entries(None::<&[&str]>)
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>();
+
+        assert_eq!(parallel.len(), 5);
+        assert_eq!(sequential, parallel);
+
+        let report = Iter::new([root.to_path_buf()].into_iter()).par_scan(None::<&[&str]>);
+        assert_eq!(report.entries.len(), 5);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].path, root.join("broken.desktop"));
+    }
+}
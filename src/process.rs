@@ -0,0 +1,454 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{DesktopEntry, FlatpakSandbox};
+
+/// Variables which launchers set to the desktop entry they launched.
+const DESKTOP_FILE_VARS: [&str; 2] = ["GIO_LAUNCHED_DESKTOP_FILE", "BAMF_DESKTOP_FILE_HINT"];
+
+/// Programs which run the script or program given as their first argument.
+const INTERPRETERS: [&str; 8] = [
+    "env", "sh", "bash", "python", "python3", "perl", "ruby", "node",
+];
+
+/// Interpreters whose `-c` option takes a command line rather than code.
+const SHELLS: [&str; 2] = ["sh", "bash"];
+
+/// What a process revealed about the application it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessEvidence {
+    /// A desktop entry from `GIO_LAUNCHED_DESKTOP_FILE` or `BAMF_DESKTOP_FILE_HINT`.
+    DesktopFile { var: &'static str, path: PathBuf },
+    /// The application ID in `/.flatpak-info` of the sandbox of the process.
+    Flatpak(String),
+    /// A snap cgroup, named `snap.<name>.<app>`.
+    Snap { name: String, app: String },
+    /// The application ID of a systemd scope or service, such as
+    /// `app-<launcher>-<id>-<random>.scope`.
+    Scope(String),
+    /// The executable of the process.
+    Executable(PathBuf),
+    /// The program of the command line, looking past interpreters.
+    CommandLine(String),
+}
+
+/// An entry which a process was resolved to, and the evidence which it matched.
+#[derive(Debug, Clone)]
+pub struct ProcessMatch<'a> {
+    pub entry: &'a DesktopEntry,
+    pub evidence: ProcessEvidence,
+}
+
+/// Resolves running processes to their desktop entries from what `/proc` reveals about them,
+/// for windows whose app ID or `WM_CLASS` is missing or wrong.
+///
+/// ```no_run
+/// use freedesktop_desktop_entry::{desktop_entries, get_languages_from_env, ProcessResolver};
+///
+/// let entries = desktop_entries(&get_languages_from_env());
+///
+/// if let Some(found) = ProcessResolver::new().resolve(std::process::id(), &entries) {
+///     println!("{} from {:?}", found.entry.id(), found.evidence);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProcessResolver {
+    proc_root: PathBuf,
+}
+
+impl Default for ProcessResolver {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            proc_root: PathBuf::from("/proc"),
+        }
+    }
+}
+
+impl ProcessResolver {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read processes from `proc_root` instead of `/proc`.
+    #[inline]
+    pub fn proc_root(mut self, proc_root: impl Into<PathBuf>) -> Self {
+        self.proc_root = proc_root.into();
+        self
+    }
+
+    /// The entry of the first evidence which matches one of `entries`.
+    pub fn resolve<'a>(&self, pid: u32, entries: &'a [DesktopEntry]) -> Option<ProcessMatch<'a>> {
+        self.evidence(pid).into_iter().find_map(|evidence| {
+            let entry = entries.iter().find(|entry| evidence.matches(entry))?;
+            Some(ProcessMatch { entry, evidence })
+        })
+    }
+
+    /// Everything which reveals the application of a process, most reliable first.
+    pub fn evidence(&self, pid: u32) -> Vec<ProcessEvidence> {
+        let dir = self.proc_root.join(pid.to_string());
+        let mut evidence = Vec::new();
+
+        if let Ok(environ) = fs::read(dir.join("environ")) {
+            for var in DESKTOP_FILE_VARS {
+                if let Some(path) = env_var(&environ, var) {
+                    evidence.push(ProcessEvidence::DesktopFile {
+                        var,
+                        path: PathBuf::from(path),
+                    });
+                }
+            }
+        }
+
+        if let Some(app_id) = FlatpakSandbox::detect_in(dir.join("root"))
+            .as_ref()
+            .and_then(FlatpakSandbox::app_id)
+        {
+            evidence.push(ProcessEvidence::Flatpak(app_id.to_owned()));
+        }
+
+        if let Ok(cgroup) = fs::read_to_string(dir.join("cgroup")) {
+            // The last component of each hierarchy, such as `0::/app.slice/app-firefox-1.scope`
+            let units = cgroup
+                .lines()
+                .filter_map(|line| line.rsplit('/').next())
+                .collect::<Vec<_>>();
+
+            evidence.extend(units.iter().filter_map(|unit| snap_unit(unit)));
+            evidence.extend(units.iter().filter_map(|unit| app_unit(unit)));
+        }
+
+        if let Ok(exe) = fs::read_link(dir.join("exe")) {
+            evidence.push(ProcessEvidence::Executable(exe));
+        }
+
+        if let Ok(cmdline) = fs::read(dir.join("cmdline")) {
+            if let Some(program) = command_program(&cmdline) {
+                evidence.push(ProcessEvidence::CommandLine(program));
+            }
+        }
+
+        evidence
+    }
+}
+
+impl ProcessEvidence {
+    /// Whether `entry` is the entry of the application which this evidence reveals.
+    pub fn matches(&self, entry: &DesktopEntry) -> bool {
+        match self {
+            ProcessEvidence::DesktopFile { path, .. } => {
+                entry.path == *path
+                    || path
+                        .file_name()
+                        .is_some_and(|name| entry.path.ends_with(name))
+            }
+            ProcessEvidence::Flatpak(app_id) => {
+                entry.flatpak() == Some(app_id) || entry.id().eq_ignore_ascii_case(app_id)
+            }
+            ProcessEvidence::Snap { name, app } => {
+                // Entries of snaps are named `<name>_<app>`, with `+` for parallel instances.
+                let id = format!("{}_{app}", name.replace('_', "+"));
+                let instance = match entry.snap_instance_name() {
+                    Some(instance) => instance == name,
+                    None => entry
+                        .id()
+                        .starts_with(&format!("{}_", name.replace('_', "+"))),
+                };
+
+                entry.id() == id || (entry.snap_appname() == Some(app) && instance)
+            }
+            ProcessEvidence::Scope(app_id) => entry.id().eq_ignore_ascii_case(app_id),
+            ProcessEvidence::Executable(exe) => entry_program(entry).is_some_and(|program| {
+                if program.is_absolute() {
+                    program == *exe
+                } else {
+                    exe.file_name() == Some(program.as_os_str())
+                }
+            }),
+            ProcessEvidence::CommandLine(name) => entry_program(entry)
+                .is_some_and(|program| program.file_name() == Some(name.as_ref())),
+        }
+    }
+}
+
+/// The program of `TryExec`, or else of `Exec`.
+#[inline]
+fn entry_program(entry: &DesktopEntry) -> Option<PathBuf> {
+    let program = entry
+        .try_exec()
+        .or_else(|| entry.exec()?.split_ascii_whitespace().next())?;
+
+    Some(PathBuf::from(program.trim_matches('"')))
+}
+
+/// The value of a variable in the NUL-separated contents of `/proc/<pid>/environ`.
+#[inline]
+fn env_var<'a>(environ: &'a [u8], name: &str) -> Option<&'a str> {
+    environ.split(|&b| b == 0).find_map(|var| {
+        let value = var.strip_prefix(name.as_bytes())?.strip_prefix(b"=")?;
+        std::str::from_utf8(value)
+            .ok()
+            .filter(|value| !value.is_empty())
+    })
+}
+
+/// A snap unit, named `snap.<name>.<app>-<uuid>.scope` or `snap.<name>.<app>.service`.
+fn snap_unit(unit: &str) -> Option<ProcessEvidence> {
+    let unit = unit.strip_prefix("snap.")?;
+    let (name, app) = unit.split_once('.')?;
+
+    let app = match app.strip_suffix(".scope") {
+        Some(scope) => strip_uuid(scope),
+        None => app.strip_suffix(".service")?,
+    };
+
+    Some(ProcessEvidence::Snap {
+        name: name.to_owned(),
+        app: app.to_owned(),
+    })
+}
+
+/// Remove the `-<uuid>` suffix of a scope. App names may contain dashes too, so the suffix is
+/// recognized by the 8-4-4-4-12 hexadecimal groups of the UUID.
+fn strip_uuid(scope: &str) -> &str {
+    let Some(split) = scope.len().checked_sub(37) else {
+        return scope;
+    };
+
+    match scope.split_at_checked(split) {
+        Some((app, suffix)) if suffix.strip_prefix('-').is_some_and(is_uuid) => app,
+        _ => scope,
+    }
+}
+
+#[inline]
+fn is_uuid(uuid: &str) -> bool {
+    uuid.split('-').map(str::len).eq([8, 4, 4, 4, 12])
+        && uuid.bytes().all(|b| b == b'-' || b.is_ascii_hexdigit())
+}
+
+/// An application unit, named `app[-<launcher>]-<id>-<random>.scope` or
+/// `app[-<launcher>]-<id>[@<random>].service`. Dashes within the ID are escaped as `\x2d`.
+fn app_unit(unit: &str) -> Option<ProcessEvidence> {
+    let unit = unit.strip_prefix("app-")?;
+
+    let name = match unit.strip_suffix(".scope") {
+        Some(scope) => scope.rsplit_once('-')?.0,
+        None => {
+            let service = unit.strip_suffix(".service")?;
+            service.split_once('@').map_or(service, |(name, _)| name)
+        }
+    };
+
+    let id = name.rsplit('-').next()?.replace("\\x2d", "-");
+    (!id.is_empty()).then_some(ProcessEvidence::Scope(id))
+}
+
+/// The file name of the program in the NUL-separated contents of `/proc/<pid>/cmdline`,
+/// skipping interpreters and their options. The program of `sh -c "<command>"` is the first
+/// word of the command.
+fn command_program(cmdline: &[u8]) -> Option<String> {
+    let file_name = |arg: &[u8]| {
+        let arg = std::str::from_utf8(arg).ok()?;
+        let name = Path::new(arg).file_name()?.to_str()?;
+        Some(name.to_owned())
+    };
+
+    let mut args = cmdline.split(|&b| b == 0).filter(|arg| !arg.is_empty());
+    let mut program = file_name(args.next()?)?;
+
+    while INTERPRETERS.contains(&program.as_str()) {
+        let Some(arg) =
+            args.find(|arg| *arg == b"-c" || (!arg.starts_with(b"-") && !arg.contains(&b'=')))
+        else {
+            break;
+        };
+
+        if arg == b"-c" {
+            if SHELLS.contains(&program.as_str()) {
+                let command = args.next()?;
+                let word = command
+                    .split(|b| b.is_ascii_whitespace())
+                    .find(|word| !word.is_empty() && !word.contains(&b'='))?;
+                program = file_name(word)?;
+            }
+
+            break;
+        }
+
+        program = file_name(arg)?;
+    }
+
+    Some(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink, path::Path};
+
+    use super::{ProcessEvidence, ProcessResolver};
+    use crate::DesktopEntry;
+
+    #[test]
+    fn resolve_processes() {
+        let temp = tempfile::tempdir().unwrap();
+        let proc = temp.path();
+
+        let write = |path: &str, contents: &[u8]| {
+            let path = proc.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+
+        write(
+            "100/environ",
+            b"HOME=/home/user\0GIO_LAUNCHED_DESKTOP_FILE=/usr/share/applications/org.gnome.Maps.desktop\0",
+        );
+        write(
+            "100/cgroup",
+            b"0::/user.slice/app.slice/app-gnome-org.gnome.Calculator-1234.scope\n",
+        );
+        write(
+            "200/cgroup",
+            b"0::/user.slice/app.slice/app-gnome-org.gnome.Calculator-1234.scope\n",
+        );
+        write(
+            "300/root/.flatpak-info",
+            b"[Application]\nname=org.gnome.Maps\n",
+        );
+        write(
+            "400/cgroup",
+            b"0::/user.slice/app.slice/snap.firefox_esr.firefox-5d4f9c1e-7a2b-4c3d-8e9f-0a1b2c3d4e5f.scope\n",
+        );
+        write(
+            "410/cgroup",
+            b"0::/user.slice/app.slice/snap.gnome-calculator.gnome-calculator-0f2c8a94-3d8c-4b6e-9a0e-1c2d3e4f5a6b.scope\n",
+        );
+        write(
+            "500/cmdline",
+            b"/usr/bin/python3\0-u\0/usr/bin/meld\0file\0",
+        );
+        write(
+            "420/cgroup",
+            b"0::/user.slice/app.slice/snap.code.code-8b2e4f6a-1c3d-4e5f-9a7b-2c4d6e8f0a1b.scope\n",
+        );
+        write("510/cmdline", b"/bin/sh\0-c\0meld --newtab file\0");
+        write("520/cmdline", b"python3\0-c\0import meld\0");
+        fs::create_dir_all(proc.join("600")).unwrap();
+        symlink("/usr/bin/gnome-calculator", proc.join("600/exe")).unwrap();
+
+        let entry = |path: &str, exec: &str| {
+            let input = format!("[Desktop Entry]\nName=App\nExec={exec}\n");
+            DesktopEntry::from_str(path, &input, None::<&[&str]>).unwrap()
+        };
+
+        let entries = [
+            entry(
+                "/usr/share/applications/org.gnome.Calculator.desktop",
+                "gnome-calculator",
+            ),
+            entry(
+                "/usr/share/applications/org.gnome.Maps.desktop",
+                "gnome-maps",
+            ),
+            entry(
+                "/var/lib/snapd/desktop/applications/firefox+esr_firefox.desktop",
+                "/snap/bin/firefox_esr.firefox",
+            ),
+            entry("/usr/share/applications/org.gnome.Meld.desktop", "meld %F"),
+            entry(
+                "/var/lib/snapd/desktop/applications/gnome-calculator_gnome-calculator.desktop",
+                "/snap/bin/gnome-calculator",
+            ),
+            DesktopEntry::from_str(
+                "/var/lib/snapd/desktop/applications/code-insiders_code.desktop",
+                "[Desktop Entry]\nName=Code\nExec=/snap/bin/code-insiders\nX-SnapAppName=code\n",
+                None::<&[&str]>,
+            )
+            .unwrap(),
+        ];
+
+        let resolver = ProcessResolver::new().proc_root(proc);
+        let resolve = |pid| {
+            let found = resolver.resolve(pid, &entries)?;
+            Some((found.entry.id().to_owned(), found.evidence))
+        };
+
+        assert_eq!(
+            resolve(100),
+            Some((
+                "org.gnome.Maps".into(),
+                ProcessEvidence::DesktopFile {
+                    var: "GIO_LAUNCHED_DESKTOP_FILE",
+                    path: "/usr/share/applications/org.gnome.Maps.desktop".into()
+                }
+            ))
+        );
+        assert_eq!(
+            resolve(200),
+            Some((
+                "org.gnome.Calculator".into(),
+                ProcessEvidence::Scope("org.gnome.Calculator".into())
+            ))
+        );
+        assert_eq!(
+            resolve(300),
+            Some((
+                "org.gnome.Maps".into(),
+                ProcessEvidence::Flatpak("org.gnome.Maps".into())
+            ))
+        );
+        assert_eq!(
+            resolve(400),
+            Some((
+                "firefox+esr_firefox".into(),
+                ProcessEvidence::Snap {
+                    name: "firefox_esr".into(),
+                    app: "firefox".into()
+                }
+            ))
+        );
+        assert_eq!(
+            resolve(410),
+            Some((
+                "gnome-calculator_gnome-calculator".into(),
+                ProcessEvidence::Snap {
+                    name: "gnome-calculator".into(),
+                    app: "gnome-calculator".into()
+                }
+            ))
+        );
+        // Another snap whose name starts with the same characters.
+        assert_eq!(resolve(420), None);
+        assert_eq!(
+            resolve(500),
+            Some((
+                "org.gnome.Meld".into(),
+                ProcessEvidence::CommandLine("meld".into())
+            ))
+        );
+        assert_eq!(
+            resolve(510),
+            Some((
+                "org.gnome.Meld".into(),
+                ProcessEvidence::CommandLine("meld".into())
+            ))
+        );
+        assert_eq!(resolve(520), None);
+        assert_eq!(
+            resolve(600),
+            Some((
+                "org.gnome.Calculator".into(),
+                ProcessEvidence::Executable(Path::new("/usr/bin/gnome-calculator").into())
+            ))
+        );
+        assert_eq!(resolve(700), None);
+    }
+}
@@ -0,0 +1,495 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    env,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use crate::{DesktopEntry, DirLayout, FileSystem, GenericEntry, RealFs};
+
+/// Where a desktop entry was installed from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Provenance {
+    pub kind: InstallKind,
+    /// The XDG data directory whose `applications` directory contains the entry, within the
+    /// sysroot of the layout if one is set.
+    pub data_dir: PathBuf,
+    /// Index of `data_dir` in the searched data directories, where 0 is the highest priority.
+    pub priority: usize,
+}
+
+/// How an application was installed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InstallKind {
+    /// Installed by the system's package manager, or by an administrator.
+    System,
+    /// Installed by the user into their data home.
+    User,
+    Flatpak {
+        /// Whether this is the per-user installation.
+        user: bool,
+        /// Name of the installation: `user`, `default` for the system-wide installation, or
+        /// the name of an installation in `/etc/flatpak/installations.d`.
+        installation: String,
+    },
+    Snap,
+    Nix {
+        /// Name of the profile, such as `system`, `user`, `default` or `per-user/alice`.
+        profile: String,
+    },
+    /// An AppImage integrated into the data home.
+    AppImage,
+    /// An application exported from a distrobox container.
+    Distrobox {
+        container: String,
+    },
+}
+
+impl Provenance {
+    /// The provenance of the file at `path`, found in one of the
+    /// [`DirLayout::application_dirs`]. Returns `None` if it is in none of them.
+    ///
+    /// AppImages and distrobox exports can only be told apart from other entries by their
+    /// contents. See [`Provenance::of_entry`].
+    #[inline]
+    pub fn from_layout(layout: &DirLayout, path: &Path) -> Option<Self> {
+        Self::from_layout_with(&RealFs, layout, path)
+    }
+
+    /// Like [`Provenance::from_layout`], reading the configuration of Flatpak installations
+    /// from the given file system.
+    pub fn from_layout_with(fs: &dyn FileSystem, layout: &DirLayout, path: &Path) -> Option<Self> {
+        layout
+            .data_search_dirs()
+            .into_iter()
+            .enumerate()
+            .find(|(_, data_dir)| path.starts_with(data_dir.join("applications")))
+            .map(|(priority, data_dir)| Self::of_data_dir(fs, layout, data_dir, priority))
+    }
+
+    /// Like [`Provenance::from_layout`], but also recognizes AppImages and distrobox exports.
+    #[inline]
+    pub fn of_entry(layout: &DirLayout, entry: &DesktopEntry) -> Option<Self> {
+        Self::of_entry_with(&RealFs, layout, entry)
+    }
+
+    /// Like [`Provenance::of_entry`], reading from the given file system.
+    #[inline]
+    pub fn of_entry_with(
+        fs: &dyn FileSystem,
+        layout: &DirLayout,
+        entry: &DesktopEntry,
+    ) -> Option<Self> {
+        Self::from_layout_with(fs, layout, &entry.path).map(|provenance| provenance.refine(entry))
+    }
+
+    /// The provenance of the entries within `data_dir`, the data directory of index `priority`
+    /// in `layout`.
+    pub(crate) fn of_data_dir(
+        fs: &dyn FileSystem,
+        layout: &DirLayout,
+        data_dir: PathBuf,
+        priority: usize,
+    ) -> Self {
+        let dir = layout.unprefixed(&data_dir);
+        let data_home = layout.data_home_dir();
+
+        let kind = if data_home == Some(dir.as_path()) {
+            InstallKind::User
+        } else if let Some(installation) = flatpak_installation(&dir) {
+            let user = data_home.is_some_and(|home| installation == home.join("flatpak"));
+            let name = if user {
+                String::from("user")
+            } else if installation == Path::new("/var/lib/flatpak") {
+                String::from("default")
+            } else {
+                installation_name(fs, layout, &installation)
+            };
+
+            InstallKind::Flatpak {
+                user,
+                installation: name,
+            }
+        } else if dir.starts_with("/var/lib/snapd") || dir.starts_with("/snap") {
+            InstallKind::Snap
+        } else if let Some(profile) = nix_profile(&dir) {
+            InstallKind::Nix { profile }
+        } else {
+            InstallKind::System
+        };
+
+        Self {
+            kind,
+            data_dir,
+            priority,
+        }
+    }
+
+    /// Recognize entries which other tools installed into the data home.
+    pub(crate) fn refine(mut self, entry: &DesktopEntry) -> Self {
+        if self.kind != InstallKind::User {
+            return self;
+        }
+
+        if let Some(container) = distrobox_container(entry) {
+            self.kind = InstallKind::Distrobox { container };
+        } else if is_appimage(entry) {
+            self.kind = InstallKind::AppImage;
+        }
+
+        self
+    }
+}
+
+#[deprecated(note = "use `Provenance`, which is derived from a `DirLayout`")]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum PathSource {
+    Local,
+    LocalDesktop,
+    LocalFlatpak,
+    LocalNix,
+    Nix,
+    System,
+    SystemLocal,
+    SystemFlatpak,
+    SystemSnap,
+    Other(String),
+}
+
+#[allow(deprecated)]
+impl PathSource {
+    /// Attempts to determine the PathSource for a given Path.
+    /// Note that this is a best-effort guesting function, and its results should be treated as
+    /// such (e.g.: non-canonical).
+    #[deprecated(note = "use `Provenance::from_layout` or `Provenance::of_entry`")]
+    pub fn guess_from(path: &Path) -> PathSource {
+        let data_home = xdg_home("XDG_DATA_HOME", ".local/share").unwrap();
+        let mut nix_state = xdg_home("XDG_STATE_HOME", ".local/state").unwrap();
+        nix_state.push("nix");
+
+        if path.starts_with("/usr/share") {
+            PathSource::System
+        } else if path.starts_with("/usr/local/share") {
+            PathSource::SystemLocal
+        } else if path.starts_with("/var/lib/flatpak") {
+            PathSource::SystemFlatpak
+        } else if path.starts_with("/var/lib/snapd") {
+            PathSource::SystemSnap
+        } else if path.starts_with("/nix/var/nix/profiles/default")
+            || path.starts_with("/nix/store")
+            || path.starts_with("/run/current-system/sw")
+        {
+            PathSource::Nix
+        } else if path.to_string_lossy().contains("/flatpak/") {
+            PathSource::LocalFlatpak
+        } else if path.starts_with(data_home.as_path()) {
+            PathSource::Local
+        } else if path.starts_with("/nix/var/nix/profiles/per-user")
+            || path.to_string_lossy().contains(".nix")
+            || path.starts_with(nix_state.as_path())
+        {
+            PathSource::LocalNix
+        } else {
+            PathSource::Other(String::from("unknown"))
+        }
+    }
+}
+
+/// The base directory in `$name`, or else `default` within the home directory.
+#[inline]
+fn xdg_home(name: &str, default: &str) -> Option<PathBuf> {
+    let absolute = |var| {
+        env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+    };
+    absolute(name).or_else(|| absolute("HOME").map(|home| home.join(default)))
+}
+
+/// The installation directory of a Flatpak export directory.
+#[inline]
+pub(crate) fn flatpak_installation(data_dir: &Path) -> Option<PathBuf> {
+    data_dir
+        .ends_with("exports/share")
+        .then(|| data_dir.parent()?.parent().map(Path::to_path_buf))
+        .flatten()
+}
+
+/// Name of a custom Flatpak installation, from the configuration in
+/// `/etc/flatpak/installations.d`, or else the name of its directory.
+fn installation_name(fs: &dyn FileSystem, layout: &DirLayout, installation: &Path) -> String {
+    let config = match layout.sysroot_dir() {
+        Some(sysroot) => sysroot.join("etc/flatpak/installations.d"),
+        None => PathBuf::from("/etc/flatpak/installations.d"),
+    };
+
+    let configured = fs
+        .read_dir(&config)
+        .into_iter()
+        .flatten()
+        .filter_map(|(file, _)| GenericEntry::from_path_with(fs, file).ok())
+        .find_map(|config| {
+            config.groups.0.iter().find_map(|(group, keys)| {
+                let name = group.strip_prefix("Installation \"")?.strip_suffix('"')?;
+                (Path::new(keys.entry("Path")?) == installation).then(|| name.to_owned())
+            })
+        });
+
+    configured.unwrap_or_else(|| {
+        installation
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    })
+}
+
+fn nix_profile(data_dir: &Path) -> Option<String> {
+    let profile = data_dir.ends_with("share").then(|| data_dir.parent())??;
+
+    if profile == Path::new("/run/current-system/sw") {
+        Some(String::from("system"))
+    } else if let Ok(name) = profile.strip_prefix("/nix/var/nix/profiles") {
+        Some(name.to_string_lossy().into_owned())
+    } else if let Ok(name) = profile.strip_prefix("/etc/profiles") {
+        Some(name.to_string_lossy().into_owned())
+    } else if profile.ends_with(".nix-profile") || profile.ends_with("nix/profile") {
+        Some(String::from("user"))
+    } else {
+        None
+    }
+}
+
+/// The container of an entry exported with `distrobox-export`, whose `Exec` enters it.
+fn distrobox_container(entry: &DesktopEntry) -> Option<String> {
+    let mut args = entry.exec()?.split_ascii_whitespace();
+
+    let program = args.next()?;
+    match Path::new(program).file_name()?.to_str()? {
+        "distrobox-enter" => (),
+        "distrobox" if args.next() == Some("enter") => (),
+        _ => return None,
+    }
+
+    while let Some(arg) = args.next() {
+        match arg {
+            "-n" | "--name" => return args.next().map(str::to_owned),
+            "--" => break,
+            _ => {
+                if let Some(name) = arg.strip_prefix("--name=") {
+                    return Some(name.to_owned());
+                }
+            }
+        }
+    }
+
+    // The default container of distrobox
+    Some(String::from("my-distrobox"))
+}
+
+/// Whether an entry was integrated by an AppImage tool, which add `X-AppImage-*` keys, or
+/// launches an AppImage.
+fn is_appimage(entry: &DesktopEntry) -> bool {
+    let has_appimage_keys = entry
+        .groups
+        .desktop_entry()
+        .is_some_and(|group| group.0.keys().any(|key| key.starts_with("X-AppImage-")));
+
+    has_appimage_keys
+        || entry
+            .try_exec()
+            .or_else(|| entry.exec()?.split_ascii_whitespace().next())
+            .is_some_and(|program| {
+                let program = program.trim_matches('"');
+                program.ends_with(".AppImage") || program.ends_with(".appimage")
+            })
+}
+
+impl Display for InstallKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InstallKind::System => f.write_str("system"),
+            InstallKind::User => f.write_str("user"),
+            InstallKind::Flatpak { installation, .. } => write!(f, "flatpak ({installation})"),
+            InstallKind::Snap => f.write_str("snap"),
+            InstallKind::Nix { profile } => write!(f, "nix ({profile})"),
+            InstallKind::AppImage => f.write_str("AppImage"),
+            InstallKind::Distrobox { container } => write!(f, "distrobox ({container})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use super::{InstallKind, Provenance};
+    use crate::{DesktopEntry, DirLayout, MemoryFs};
+
+    #[test]
+    fn provenance_from_layout() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let config = root.join("etc/flatpak/installations.d/extra.conf");
+        fs::create_dir_all(config.parent().unwrap()).unwrap();
+        fs::write(
+            &config,
+            "[Installation \"steamdeck\"]\nPath=/run/media/flatpak\n",
+        )
+        .unwrap();
+
+        let layout = DirLayout::new()
+            .data_home("/home/user/.local/share")
+            .data_dirs(
+                [
+                    "/home/user/.local/share/flatpak/exports/share",
+                    "/var/lib/flatpak/exports/share",
+                    "/run/media/flatpak/exports/share",
+                    "/var/lib/snapd/desktop",
+                    "/nix/var/nix/profiles/default/share",
+                    "/usr/share",
+                ]
+                .map(PathBuf::from),
+            )
+            .sysroot(root);
+
+        let kind = |path: &str| {
+            Provenance::from_layout(&layout, &root.join(path)).map(|provenance| provenance.kind)
+        };
+
+        let flatpak = |user, installation: &str| {
+            Some(InstallKind::Flatpak {
+                user,
+                installation: installation.into(),
+            })
+        };
+
+        assert_eq!(
+            kind("home/user/.local/share/applications/a.desktop"),
+            Some(InstallKind::User)
+        );
+        assert_eq!(
+            kind("home/user/.local/share/flatpak/exports/share/applications/a.desktop"),
+            flatpak(true, "user")
+        );
+        assert_eq!(
+            kind("var/lib/flatpak/exports/share/applications/a.desktop"),
+            flatpak(false, "default")
+        );
+        assert_eq!(
+            kind("run/media/flatpak/exports/share/applications/a.desktop"),
+            flatpak(false, "steamdeck")
+        );
+        assert_eq!(
+            kind("var/lib/snapd/desktop/applications/a.desktop"),
+            Some(InstallKind::Snap)
+        );
+        assert_eq!(
+            kind("nix/var/nix/profiles/default/share/applications/a.desktop"),
+            Some(InstallKind::Nix {
+                profile: "default".into()
+            })
+        );
+        assert_eq!(
+            kind("usr/share/applications/kde/a.desktop"),
+            Some(InstallKind::System)
+        );
+        assert_eq!(kind("opt/a.desktop"), None);
+
+        let provenance =
+            Provenance::from_layout(&layout, &root.join("usr/share/applications/a.desktop"))
+                .unwrap();
+        assert_eq!(provenance.priority, 6);
+        assert_eq!(provenance.data_dir, root.join("usr/share"));
+
+        // The configuration of installations is read from the given file system.
+        let memory = MemoryFs::new();
+        memory.add_file(
+            "/etc/flatpak/installations.d/extra.conf",
+            "[Installation \"sdcard\"]\nPath=/run/media/flatpak\n",
+        );
+        let layout = DirLayout::new().data_dirs(["/run/media/flatpak/exports/share".into()]);
+        assert_eq!(
+            Provenance::from_layout_with(
+                &memory,
+                &layout,
+                Path::new("/run/media/flatpak/exports/share/applications/a.desktop")
+            )
+            .map(|provenance| provenance.kind),
+            flatpak(false, "sdcard")
+        );
+    }
+
+    #[test]
+    fn provenance_of_user_entries() {
+        let layout = DirLayout::new().data_home("/home/user/.local/share");
+
+        let kind = |exec: &str| {
+            let input = format!("[Desktop Entry]\nName=App\nExec={exec}\n");
+            let entry = DesktopEntry::from_str(
+                "/home/user/.local/share/applications/app.desktop",
+                &input,
+                None::<&[&str]>,
+            )
+            .unwrap();
+            Provenance::of_entry(&layout, &entry).unwrap().kind
+        };
+
+        assert_eq!(kind("gimp %U"), InstallKind::User);
+        assert_eq!(
+            kind("/usr/bin/distrobox-enter  -n ubuntu  --   /usr/bin/gimp %U"),
+            InstallKind::Distrobox {
+                container: "ubuntu".into()
+            }
+        );
+        assert_eq!(
+            kind("/home/user/Applications/Krita-5.2.AppImage %F"),
+            InstallKind::AppImage
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn guess_path_source() {
+        use super::PathSource;
+
+        let source = |path: &str| PathSource::guess_from(Path::new(path));
+
+        assert_eq!(
+            source("/usr/share/applications/firefox.desktop"),
+            PathSource::System
+        );
+        assert_eq!(
+            source("/usr/local/share/applications/kde/konsole.desktop"),
+            PathSource::SystemLocal
+        );
+        assert_eq!(
+            source("/var/lib/flatpak/exports/share/applications/org.gnome.Maps.desktop"),
+            PathSource::SystemFlatpak
+        );
+        assert_eq!(
+            source("/var/lib/snapd/desktop/applications/firefox_firefox.desktop"),
+            PathSource::SystemSnap
+        );
+        assert_eq!(
+            source("/run/current-system/sw/share/applications/vim.desktop"),
+            PathSource::Nix
+        );
+        assert_eq!(
+            source("/opt/flatpak/exports/share/applications/org.gnome.Maps.desktop"),
+            PathSource::LocalFlatpak
+        );
+        assert_eq!(
+            source("/opt/user/.nix-profile/share/applications/vim.desktop"),
+            PathSource::LocalNix
+        );
+        assert_eq!(
+            source("/opt/app/app.desktop"),
+            PathSource::Other("unknown".into())
+        );
+    }
+}
@@ -0,0 +1,351 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use crate::{DesktopEntry, DesktopFileId, DirLayout, FileSystem, Iter, Provenance, RealFs};
+
+/// The desktop entries which are installed, after applying the rules of the [specification]:
+/// the first file found for a desktop file ID wins, and an entry with `Hidden=true` deletes
+/// its ID altogether.
+///
+/// ```no_run
+/// use freedesktop_desktop_entry::{get_languages_from_env, AppRegistry};
+///
+/// let registry = AppRegistry::new(&get_languages_from_env());
+///
+/// if let Some(firefox) = registry.get("org.mozilla.firefox") {
+///     println!("{}", firefox.path.display());
+///
+///     for candidate in registry.shadowed("org.mozilla.firefox") {
+///         println!("overrides {}", candidate.path.display());
+///     }
+/// }
+/// ```
+///
+/// [specification]: https://specifications.freedesktop.org/desktop-entry-spec/latest/file-naming.html
+#[derive(Debug, Clone, Default)]
+pub struct AppRegistry {
+    apps: BTreeMap<DesktopFileId, DesktopEntry>,
+    candidates: BTreeMap<DesktopFileId, Vec<Candidate>>,
+}
+
+/// A file providing a desktop file ID, and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub path: PathBuf,
+    /// The data directory the file was found in, and how it was installed.
+    pub provenance: Provenance,
+    pub state: CandidateState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidateState {
+    /// This file provides the entry of the registry.
+    Active,
+    /// This file sets `Hidden=true`, deleting its ID.
+    Masked,
+    /// A file of higher priority provides or masks the same ID.
+    Shadowed,
+    /// This file could not be loaded, so the next candidate was tried.
+    Invalid,
+}
+
+impl AppRegistry {
+    /// Load the entries of the [`crate::default_paths`], with only the given locales retained.
+    #[inline]
+    pub fn new(locales: &[String]) -> Self {
+        Self::from_layout(&DirLayout::from_env_lossy(), Some(locales))
+    }
+
+    /// Load the entries of the [`DirLayout::application_dirs`].
+    #[inline]
+    pub fn from_layout<L: AsRef<str>>(layout: &DirLayout, locales_filter: Option<&[L]>) -> Self {
+        Self::from_layout_with(Arc::new(RealFs), layout, locales_filter)
+    }
+
+    /// Like [`AppRegistry::from_layout`], reading from the given file system.
+    #[inline]
+    pub fn from_layout_with<L: AsRef<str>>(
+        fs: Arc<dyn FileSystem>,
+        layout: &DirLayout,
+        locales_filter: Option<&[L]>,
+    ) -> Self {
+        Self::load(fs, layout, layout.application_dirs(), locales_filter)
+    }
+
+    /// Load the entries of the given `applications` directories, in order of priority.
+    #[inline]
+    pub fn from_dirs<I, L>(directories: I, locales_filter: Option<&[L]>) -> Self
+    where
+        I: IntoIterator<Item = PathBuf>,
+        L: AsRef<str>,
+    {
+        Self::from_dirs_with(Arc::new(RealFs), directories, locales_filter)
+    }
+
+    /// Like [`AppRegistry::from_dirs`], reading from the given file system.
+    ///
+    /// The [`Provenance`] of candidates is derived from the directories alone. As none of them
+    /// is known to be the data home, no candidate is [`InstallKind::User`](crate::InstallKind).
+    /// Use [`AppRegistry::from_layout_with`] to recognize it.
+    #[inline]
+    pub fn from_dirs_with<I, L>(
+        fs: Arc<dyn FileSystem>,
+        directories: I,
+        locales_filter: Option<&[L]>,
+    ) -> Self
+    where
+        I: IntoIterator<Item = PathBuf>,
+        L: AsRef<str>,
+    {
+        Self::load(
+            fs,
+            &DirLayout::new(),
+            directories.into_iter().collect(),
+            locales_filter,
+        )
+    }
+
+    fn load<L: AsRef<str>>(
+        fs: Arc<dyn FileSystem>,
+        layout: &DirLayout,
+        directories: Vec<PathBuf>,
+        locales_filter: Option<&[L]>,
+    ) -> Self {
+        let provenances = directories
+            .iter()
+            .enumerate()
+            .map(|(priority, dir)| {
+                let data_dir = if dir.ends_with("applications") {
+                    dir.parent().unwrap_or(dir)
+                } else {
+                    dir
+                };
+
+                Provenance::of_data_dir(&*fs, layout, data_dir.to_path_buf(), priority)
+            })
+            .collect::<Vec<_>>();
+
+        let mut iter = Iter::builder(directories.clone())
+            .filesystem(fs.clone())
+            .build();
+        let mut registry = Self::default();
+
+        while let Some((path, root)) = iter.next_with_root() {
+            let Ok(id) = DesktopFileId::from_path(&root, &path) else {
+                continue;
+            };
+
+            let Some(provenance) = directories
+                .iter()
+                .position(|dir| dir.as_path() == &*root)
+                .map(|priority| provenances[priority].clone())
+            else {
+                continue;
+            };
+
+            registry.candidates.entry(id).or_default().push(Candidate {
+                path,
+                provenance,
+                state: CandidateState::Shadowed,
+            });
+        }
+
+        for (id, candidates) in &mut registry.candidates {
+            for candidate in candidates.iter_mut() {
+                match DesktopEntry::from_path_with(&*fs, &candidate.path, locales_filter) {
+                    Ok(entry) if entry.hidden() => {
+                        candidate.state = CandidateState::Masked;
+                    }
+                    Ok(mut entry) => {
+                        candidate.state = CandidateState::Active;
+                        candidate.provenance = candidate.provenance.clone().refine(&entry);
+                        entry.appid = id.to_string();
+                        registry.apps.insert(id.clone(), entry);
+                    }
+                    Err(why) => {
+                        log::debug!("skipping {}: {why}", candidate.path.display());
+                        candidate.state = CandidateState::Invalid;
+                        continue;
+                    }
+                }
+
+                break;
+            }
+        }
+
+        registry
+    }
+
+    /// The entry providing `id`, unless it is masked or not installed.
+    #[inline]
+    pub fn get(&self, id: &str) -> Option<&DesktopEntry> {
+        self.apps.get(id)
+    }
+
+    #[inline]
+    pub fn contains(&self, id: &str) -> bool {
+        self.apps.contains_key(id)
+    }
+
+    /// Whether `id` is installed, but deleted with `Hidden=true`.
+    #[inline]
+    pub fn is_masked(&self, id: &str) -> bool {
+        self.candidates(id)
+            .iter()
+            .any(|candidate| candidate.state == CandidateState::Masked)
+    }
+
+    /// Every file providing `id`, in order of priority.
+    #[inline]
+    pub fn candidates(&self, id: &str) -> &[Candidate] {
+        self.candidates.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The files providing `id` which are overridden by one of higher priority.
+    #[inline]
+    pub fn shadowed(&self, id: &str) -> impl Iterator<Item = &Candidate> {
+        self.candidates(id)
+            .iter()
+            .filter(|candidate| candidate.state == CandidateState::Shadowed)
+    }
+
+    /// The file providing the entry of `id`, if any.
+    #[inline]
+    pub fn active(&self, id: &str) -> Option<&Candidate> {
+        self.candidates(id)
+            .iter()
+            .find(|candidate| candidate.state == CandidateState::Active)
+    }
+
+    /// Visible entries, sorted by ID.
+    #[inline]
+    pub fn entries(&self) -> impl Iterator<Item = &DesktopEntry> {
+        self.apps.values()
+    }
+
+    /// IDs of the visible entries, in sorted order.
+    #[inline]
+    pub fn ids(&self) -> impl Iterator<Item = &DesktopFileId> {
+        self.apps.keys()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.apps.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.apps.is_empty()
+    }
+
+    /// Only keep the entries for which `keep` returns `true`, such as those whose program
+    /// [resolves](DesktopEntry::resolve_executable) or which [should be
+    /// shown](DesktopEntry::should_show). Candidates are not affected.
+    #[inline]
+    pub fn retain<F: FnMut(&DesktopEntry) -> bool>(&mut self, mut keep: F) {
+        self.apps.retain(|_, entry| keep(entry));
+    }
+
+    #[inline]
+    pub fn into_entries(self) -> Vec<DesktopEntry> {
+        self.apps.into_values().collect()
+    }
+}
+
+impl Candidate {
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.state == CandidateState::Active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{AppRegistry, CandidateState};
+    use crate::{DirLayout, InstallKind};
+
+    #[test]
+    fn registry_shadows_and_masks() {
+        let temp = tempfile::tempdir().unwrap();
+        let user = temp.path().join("user");
+        let system = temp.path().join("system");
+        fs::create_dir_all(system.join("kde")).unwrap();
+        fs::create_dir_all(&user).unwrap();
+
+        let entry = |name: &str| format!("[Desktop Entry]\nType=Application\nName={name}\n");
+        fs::write(user.join("firefox.desktop"), entry("User Firefox")).unwrap();
+        fs::write(system.join("firefox.desktop"), entry("Firefox")).unwrap();
+        fs::write(system.join("kde/konsole.desktop"), entry("Konsole")).unwrap();
+        fs::write(user.join("broken.desktop"), "Name=No Group\n").unwrap();
+        fs::write(system.join("broken.desktop"), entry("Fixed")).unwrap();
+        fs::write(
+            user.join("kde-konsole.desktop"),
+            "[Desktop Entry]\nHidden=true\n",
+        )
+        .unwrap();
+
+        let registry = AppRegistry::from_dirs([user.clone(), system.clone()], None::<&[&str]>);
+
+        assert_eq!(
+            registry.ids().map(|id| id.as_str()).collect::<Vec<_>>(),
+            ["broken", "firefox"]
+        );
+
+        let firefox = registry.get("firefox").unwrap();
+        assert_eq!(firefox.name(&[] as &[&str]).unwrap(), "User Firefox");
+        assert_eq!(registry.active("firefox").unwrap().provenance.priority, 0);
+
+        let shadowed = registry.shadowed("firefox").collect::<Vec<_>>();
+        assert_eq!(shadowed.len(), 1);
+        assert_eq!(shadowed[0].path, system.join("firefox.desktop"));
+        assert_eq!(shadowed[0].provenance.data_dir, system);
+        assert_eq!(shadowed[0].provenance.priority, 1);
+        assert_eq!(shadowed[0].provenance.kind, InstallKind::System);
+
+        assert!(registry.is_masked("kde-konsole"));
+        assert!(registry.get("kde-konsole").is_none());
+        assert_eq!(
+            registry
+                .candidates("kde-konsole")
+                .iter()
+                .map(|candidate| candidate.state)
+                .collect::<Vec<_>>(),
+            [CandidateState::Masked, CandidateState::Shadowed]
+        );
+
+        assert_eq!(
+            registry.candidates("broken")[0].state,
+            CandidateState::Invalid
+        );
+        assert_eq!(
+            registry
+                .get("broken")
+                .unwrap()
+                .name(&[] as &[&str])
+                .unwrap(),
+            "Fixed"
+        );
+
+        // Only a layout tells which directory is the data home.
+        let layout = DirLayout::new().data_home(&user);
+        fs::create_dir_all(user.join("applications")).unwrap();
+        fs::write(user.join("applications/app.desktop"), entry("App")).unwrap();
+
+        let registry = AppRegistry::from_layout(&layout, None::<&[&str]>);
+        assert_eq!(
+            registry.active("app").unwrap().provenance.kind,
+            InstallKind::User
+        );
+
+        let registry = AppRegistry::from_dirs(layout.application_dirs(), None::<&[&str]>);
+        assert_eq!(
+            registry.active("app").unwrap().provenance.kind,
+            InstallKind::System
+        );
+    }
+}
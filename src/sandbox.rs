@@ -0,0 +1,286 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{DesktopEntry, DirLayout, ExecError, GenericEntry, Iter};
+
+/// Where the host's `/usr` and `/etc` are mounted within a Flatpak sandbox.
+const HOST_PREFIX: &str = "/run/host";
+
+/// Host directories which Flatpak makes visible under [`HOST_PREFIX`].
+const HOST_MOUNTS: [&str; 2] = ["/usr", "/etc"];
+
+/// A Flatpak sandbox which this process runs in, used to discover and launch the applications
+/// of the host.
+///
+/// Within the sandbox, [`crate::default_paths`] only finds the applications of the sandbox
+/// itself. The host's entries are read through `/run/host` and the Flatpak export directories,
+/// which requires the `host-os` (or `host`) and Flatpak installation file system permissions.
+///
+/// ```no_run
+/// use freedesktop_desktop_entry::{get_languages_from_env, FlatpakSandbox};
+///
+/// if let Some(sandbox) = FlatpakSandbox::detect() {
+///     let locales = get_languages_from_env();
+///     for entry in sandbox.host_entries(&locales) {
+///         let command = sandbox.spawn_command(&entry, &[], &locales);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatpakSandbox {
+    sysroot: Option<PathBuf>,
+    app_id: Option<String>,
+}
+
+impl FlatpakSandbox {
+    /// Detect whether this process runs in a Flatpak sandbox, by the presence of
+    /// `/.flatpak-info`.
+    #[inline]
+    pub fn detect() -> Option<Self> {
+        Self::detect_at(None)
+    }
+
+    /// Like [`FlatpakSandbox::detect`], for a sandbox whose root directory is `sysroot`.
+    #[inline]
+    pub fn detect_in(sysroot: impl Into<PathBuf>) -> Option<Self> {
+        Self::detect_at(Some(sysroot.into()))
+    }
+
+    fn detect_at(sysroot: Option<PathBuf>) -> Option<Self> {
+        let info = match sysroot.as_ref() {
+            Some(sysroot) => sysroot.join(".flatpak-info"),
+            None => PathBuf::from("/.flatpak-info"),
+        };
+
+        if !info.is_file() {
+            return None;
+        }
+
+        let app_id = GenericEntry::from_path(info).ok().and_then(|info| {
+            info.group("Application")
+                .and_then(|group| group.entry("name"))
+                .map(str::to_owned)
+        });
+
+        Some(Self { sysroot, app_id })
+    }
+
+    /// ID of the Flatpak application which this process belongs to.
+    #[inline]
+    pub fn app_id(&self) -> Option<&str> {
+        self.app_id.as_deref()
+    }
+
+    /// Directories of the host, as seen from within the sandbox. Uses the `HOST_XDG_*`
+    /// variables which Flatpak sets, falling back to the defaults within `HOME`.
+    #[inline]
+    pub fn host_layout(&self) -> DirLayout {
+        self.host_layout_from(|name| env::var_os(name))
+    }
+
+    fn host_layout_from(&self, var: impl Fn(&str) -> Option<OsString>) -> DirLayout {
+        let home = var("HOME").map(PathBuf::from).filter(|p| p.is_absolute());
+
+        let home_dir = |name: &str, default: &str| {
+            var(name)
+                .map(PathBuf::from)
+                .filter(|p| p.is_absolute())
+                .or_else(|| home.as_ref().map(|home| home.join(default)))
+        };
+
+        let data_home = home_dir("HOST_XDG_DATA_HOME", ".local/share");
+        let config_home = home_dir("HOST_XDG_CONFIG_HOME", ".config");
+
+        let data_dirs = data_home
+            .iter()
+            .map(|data_home| data_home.join("flatpak/exports/share"))
+            .chain(
+                [
+                    "/var/lib/flatpak/exports/share",
+                    "/run/host/usr/local/share",
+                    "/run/host/usr/share",
+                ]
+                .map(PathBuf::from),
+            );
+
+        let mut layout = DirLayout::new()
+            .data_dirs(data_dirs.collect::<Vec<_>>())
+            .config_dirs([PathBuf::from("/run/host/etc/xdg")]);
+
+        if let Some(data_home) = data_home {
+            layout = layout.data_home(data_home);
+        }
+
+        if let Some(config_home) = config_home {
+            layout = layout.config_home(config_home);
+        }
+
+        match self.sysroot.as_ref() {
+            Some(sysroot) => layout.sysroot(sysroot),
+            None => layout,
+        }
+    }
+
+    /// Load the entries of the host, with their icons rewritten to be readable within the
+    /// sandbox.
+    pub fn host_entries<L: AsRef<str>>(&self, locales: &[L]) -> Vec<DesktopEntry> {
+        Iter::new(self.host_layout().application_dirs().into_iter())
+            .entries(Some(locales))
+            .map(|mut entry| {
+                self.rewrite_entry(&mut entry);
+                entry
+            })
+            .collect()
+    }
+
+    /// Where a path of the host can be read within the sandbox.
+    pub fn to_sandbox_path(&self, host_path: &Path) -> PathBuf {
+        let path = if HOST_MOUNTS.iter().any(|mount| host_path.starts_with(mount)) {
+            Path::new(HOST_PREFIX).join(host_path.strip_prefix("/").unwrap_or(host_path))
+        } else {
+            host_path.to_path_buf()
+        };
+
+        match self.sysroot.as_ref() {
+            Some(sysroot) => sysroot.join(path.strip_prefix("/").unwrap_or(&path)),
+            None => path,
+        }
+    }
+
+    /// The path on the host of a path within the sandbox. The inverse of
+    /// [`FlatpakSandbox::to_sandbox_path`].
+    pub fn to_host_path(&self, sandbox_path: &Path) -> PathBuf {
+        let path = match self.sysroot.as_ref() {
+            Some(sysroot) => match sandbox_path.strip_prefix(sysroot) {
+                Ok(path) => Path::new("/").join(path),
+                Err(_) => sandbox_path.to_path_buf(),
+            },
+            None => sandbox_path.to_path_buf(),
+        };
+
+        match path.strip_prefix(HOST_PREFIX) {
+            Ok(host_path) => Path::new("/").join(host_path),
+            Err(_) => path,
+        }
+    }
+
+    /// Rewrite an `Icon` which is an absolute path of the host, so that it can be read within
+    /// the sandbox.
+    pub fn rewrite_entry(&self, entry: &mut DesktopEntry) {
+        let Some(icon) = entry
+            .groups
+            .0
+            .get_mut("Desktop Entry")
+            .and_then(|group| group.0.get_mut("Icon"))
+        else {
+            return;
+        };
+
+        let (default, localized) = icon;
+        for value in std::iter::once(default).chain(localized.values_mut()) {
+            if Path::new(value).is_absolute() {
+                if let Some(path) = self.to_sandbox_path(Path::new(value)).to_str() {
+                    *value = path.to_owned();
+                }
+            }
+        }
+    }
+
+    /// Arguments which launch `entry` on the host with `flatpak-spawn --host`. The `%k` field
+    /// code expands to the path of the entry on the host, and the `Path` key becomes the
+    /// working directory.
+    pub fn spawn_command<L: AsRef<str>>(
+        &self,
+        entry: &DesktopEntry,
+        uris: &[&str],
+        locales: &[L],
+    ) -> Result<Vec<String>, ExecError> {
+        let host_entry = DesktopEntry {
+            path: self.to_host_path(&entry.path),
+            ..entry.clone()
+        };
+
+        let mut command = vec![String::from("flatpak-spawn"), String::from("--host")];
+
+        if let Some(dir) = host_entry.path() {
+            command.push(format!("--directory={dir}"));
+        }
+
+        command.extend(host_entry.parse_exec_with_uris(uris, locales)?);
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, ffi::OsString, fs, path::Path};
+
+    use super::FlatpakSandbox;
+
+    #[test]
+    fn sandbox_reads_host_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        assert!(FlatpakSandbox::detect_in(root).is_none());
+
+        fs::write(
+            root.join(".flatpak-info"),
+            "[Application]\nname=com.system76.Launcher\n",
+        )
+        .unwrap();
+
+        let host_apps = root.join("run/host/usr/share/applications");
+        fs::create_dir_all(&host_apps).unwrap();
+        fs::write(
+            host_apps.join("org.gnome.Maps.desktop"),
+            "[Desktop Entry]\nName=Maps\nExec=gnome-maps %U\nIcon=/usr/share/maps.svg\nPath=/tmp\n",
+        )
+        .unwrap();
+
+        let sandbox = FlatpakSandbox::detect_in(root).unwrap();
+        assert_eq!(sandbox.app_id(), Some("com.system76.Launcher"));
+
+        let vars = HashMap::from([("HOME", OsString::from("/home/user"))]);
+        let layout = sandbox.host_layout_from(|name| vars.get(name).cloned());
+        assert_eq!(
+            layout.application_dirs(),
+            [
+                root.join("home/user/.local/share/applications"),
+                root.join("home/user/.local/share/flatpak/exports/share/applications"),
+                root.join("var/lib/flatpak/exports/share/applications"),
+                root.join("run/host/usr/local/share/applications"),
+                host_apps.clone(),
+            ]
+        );
+
+        let entries = sandbox.host_entries(&[] as &[&str]);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            Path::new(entries[0].icon().unwrap()),
+            root.join("run/host/usr/share/maps.svg")
+        );
+
+        assert_eq!(
+            sandbox
+                .spawn_command(&entries[0], &["https://maps.example"], &[] as &[&str])
+                .unwrap(),
+            [
+                "flatpak-spawn",
+                "--host",
+                "--directory=/tmp",
+                "gnome-maps",
+                "https://maps.example"
+            ]
+        );
+        assert_eq!(
+            sandbox.to_host_path(&entries[0].path),
+            Path::new("/usr/share/applications/org.gnome.Maps.desktop")
+        );
+    }
+}
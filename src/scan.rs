@@ -0,0 +1,231 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    iter::SkippedDir, DecodeError, DesktopEntry, DesktopFileId, DirLayout, FileSystem, IdError,
+    Iter,
+};
+
+/// A desktop entry which was found but could not be loaded.
+#[derive(Debug, Error)]
+#[error("{}: {kind}", path.display())]
+pub struct ScanError {
+    pub path: PathBuf,
+    #[source]
+    pub kind: ScanErrorKind,
+}
+
+#[derive(Debug, Error)]
+pub enum ScanErrorKind {
+    #[error("permission denied")]
+    PermissionDenied(#[source] io::Error),
+    #[error(transparent)]
+    Io(io::Error),
+    #[error("file is not valid UTF-8 after byte {valid_up_to}")]
+    NonUtf8 { valid_up_to: usize },
+    #[error(transparent)]
+    Decode(DecodeError),
+}
+
+impl From<io::Error> for ScanErrorKind {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::PermissionDenied {
+            ScanErrorKind::PermissionDenied(error)
+        } else {
+            ScanErrorKind::Io(error)
+        }
+    }
+}
+
+impl From<DecodeError> for ScanErrorKind {
+    fn from(error: DecodeError) -> Self {
+        match error {
+            DecodeError::Io(error) => error.into(),
+            error => ScanErrorKind::Decode(error),
+        }
+    }
+}
+
+/// Iterator returned by [`Iter::scan_entries`].
+pub struct ScanEntries<'l, L> {
+    iter: Iter,
+    locales_filter: Option<&'l [L]>,
+}
+
+impl<'l, L: AsRef<str>> ScanEntries<'l, L> {
+    #[inline]
+    pub(crate) fn new(iter: Iter, locales_filter: Option<&'l [L]>) -> Self {
+        Self {
+            iter,
+            locales_filter,
+        }
+    }
+
+    /// Directories which could not be read so far, including those which do not exist.
+    #[inline]
+    pub fn skipped_dirs(&self) -> &[SkippedDir] {
+        self.iter.skipped_dirs()
+    }
+
+    /// Consume the iterator, returning the directories which could not be read.
+    #[inline]
+    pub fn into_skipped_dirs(self) -> Vec<SkippedDir> {
+        self.iter.into_skipped_dirs()
+    }
+}
+
+impl<L: AsRef<str>> Iterator for ScanEntries<'_, L> {
+    type Item = Result<DesktopEntry, ScanError>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, id) = self.iter.next_with_id()?;
+        let fs = &**self.iter.filesystem();
+        Some(load(fs, &path, id, self.locales_filter).map_err(|kind| ScanError { path, kind }))
+    }
+}
+
+#[inline(never)]
+pub(crate) fn load<L: AsRef<str>>(
+    fs: &dyn FileSystem,
+    path: &Path,
+    id: Result<DesktopFileId, IdError>,
+    locales_filter: Option<&[L]>,
+) -> Result<DesktopEntry, ScanErrorKind> {
+    let id = id.map_err(|_| DecodeError::AppID)?;
+    let bytes = fs.read(path)?;
+    let input = String::from_utf8(bytes).map_err(|error| ScanErrorKind::NonUtf8 {
+        valid_up_to: error.utf8_error().valid_up_to(),
+    })?;
+
+    let mut entry = DesktopEntry::from_str(path, &input, locales_filter)?;
+    entry.appid = id.into();
+    Ok(entry)
+}
+
+/// Result of [`scan_desktop_entries`].
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// Entries which were loaded successfully.
+    pub entries: Vec<DesktopEntry>,
+    /// Entries which were found but could not be loaded.
+    pub errors: Vec<ScanError>,
+    /// Directories which could not be read, including those which do not exist.
+    pub skipped_dirs: Vec<SkippedDir>,
+}
+
+/// Like [`crate::desktop_entries`], but also reports every entry and directory which was skipped.
+#[inline]
+pub fn scan_desktop_entries(locales: &[String]) -> ScanReport {
+    scan_desktop_entries_in(&DirLayout::from_env_lossy(), locales)
+}
+
+/// Like [`scan_desktop_entries`], reading the [`DirLayout::application_dirs`] of `layout`.
+pub fn scan_desktop_entries_in(layout: &DirLayout, locales: &[String]) -> ScanReport {
+    let mut report = ScanReport::default();
+    let mut scan = Iter::new(layout.application_dirs().into_iter()).scan_entries(Some(locales));
+
+    for result in &mut scan {
+        match result {
+            Ok(entry) => report.entries.push(entry),
+            Err(error) => report.errors.push(error),
+        }
+    }
+
+    report.skipped_dirs = scan.into_skipped_dirs();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn scan_reports_failures() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let missing = root.join("missing");
+
+        let valid = root.join("valid.desktop");
+        fs::write(&valid, "[Desktop Entry]\nName=Valid\n").unwrap();
+        let invalid = root.join("invalid.desktop");
+        fs::write(&invalid, "[Desktop Entry]\nName=Invalid\\q\n").unwrap();
+        let non_utf8 = root.join("non-utf8.desktop");
+        fs::write(&non_utf8, b"[Desktop Entry]\nName=\xff\n").unwrap();
+
+        let mut scan = Iter::new([root.to_path_buf(), missing.clone()].into_iter())
+            .scan_entries(None::<&[&str]>);
+
+        let invalid_error = scan.next().unwrap().unwrap_err();
+        assert_eq!(invalid_error.path, invalid);
+        match invalid_error.kind {
+            ScanErrorKind::Decode(DecodeError::InvalidValue(span)) => assert_eq!(span.line, 2),
+            kind => panic!("unexpected error: {kind:?}"),
+        }
+
+        let non_utf8_error = scan.next().unwrap().unwrap_err();
+        assert_eq!(non_utf8_error.path, non_utf8);
+        assert!(matches!(
+            non_utf8_error.kind,
+            ScanErrorKind::NonUtf8 { valid_up_to: 21 }
+        ));
+
+        assert_eq!(scan.next().unwrap().unwrap().path, valid);
+        assert!(scan.next().is_none());
+
+        let skipped = scan.into_skipped_dirs();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].path, missing);
+        assert_eq!(skipped[0].error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn scan_layout() {
+        let temp = tempfile::tempdir().unwrap();
+        let applications = temp.path().join("applications");
+        fs::create_dir(&applications).unwrap();
+        fs::write(
+            applications.join("app.desktop"),
+            "[Desktop Entry]\nName=App\n",
+        )
+        .unwrap();
+
+        let layout = DirLayout::new()
+            .data_home(temp.path())
+            .data_dirs([temp.path().join("missing")]);
+        let report = scan_desktop_entries_in(&layout, &[]);
+
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].id(), "app");
+        assert_eq!(report.skipped_dirs.len(), 1);
+    }
+
+    #[test]
+    fn scan_reports_dangling_links() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        let valid = root.join("valid.desktop");
+        fs::write(&valid, "[Desktop Entry]\nName=Valid\n").unwrap();
+        let dangling = root.join("dangling");
+        std::os::unix::fs::symlink(root.join("nowhere"), &dangling).unwrap();
+
+        let mut scan = Iter::new(std::iter::once(root.to_path_buf())).scan_entries(None::<&[&str]>);
+        assert_eq!(scan.next().unwrap().unwrap().path, valid);
+        assert!(scan.next().is_none());
+
+        let skipped = scan.into_skipped_dirs();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].path, dangling);
+        assert_eq!(skipped[0].error.kind(), io::ErrorKind::NotFound);
+    }
+}
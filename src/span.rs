@@ -0,0 +1,127 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    fmt::{self, Display, Formatter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// Location of an error within the decoded input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    /// File the input was read from, if known.
+    pub path: Option<PathBuf>,
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// Column of the first offending character, starting at 1.
+    pub column: usize,
+    /// Byte range of the offending text within the whole input.
+    pub bytes: Range<usize>,
+    /// The offending text.
+    pub text: String,
+    /// The complete line containing the offending text.
+    pub source_line: String,
+}
+
+impl Span {
+    /// Create a span covering `range` of `line`, which starts at byte `offset` of the input.
+    pub(crate) fn new(
+        path: Option<&Path>,
+        line_number: usize,
+        offset: usize,
+        line: &str,
+        range: Range<usize>,
+    ) -> Self {
+        Span {
+            path: path.map(Path::to_path_buf),
+            line: line_number,
+            column: line[..range.start].chars().count() + 1,
+            bytes: offset + range.start..offset + range.end,
+            text: line[range].to_string(),
+            source_line: line.to_string(),
+        }
+    }
+
+    /// Render `message` followed by an excerpt of the source line with a caret under the
+    /// offending text. For example, `span.render(&"invalid value")` gives:
+    ///
+    /// ```txt
+    /// error: invalid value
+    ///   |
+    /// 3 | Name=Foo\q
+    ///   |         ^^
+    /// ```
+    pub fn render(&self, message: &dyn Display) -> String {
+        let gutter = self.line.to_string().len();
+        let underline = self.text.chars().count().max(1);
+        let mut out = String::new();
+
+        let _ = writeln!(out, "error: {message}");
+        let _ = writeln!(out, "{:gutter$} |", "");
+        let _ = writeln!(out, "{} | {}", self.line, self.source_line);
+        let _ = writeln!(
+            out,
+            "{:gutter$} | {:pad$}{:^<underline$}",
+            "",
+            "",
+            "",
+            pad = self.column - 1
+        );
+
+        out
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Like [`str::lines`], but also yields the byte offset at which each line starts.
+pub(crate) fn lines_with_offsets(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input.split_inclusive('\n').scan(0, |offset, raw| {
+        let start = *offset;
+        *offset += raw.len();
+        let line = raw.strip_suffix('\n').unwrap_or(raw);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        Some((start, line))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_with_offsets_matches_lines() {
+        let input = "[Group]\r\nKey=Value\n\nLast";
+        let lines = lines_with_offsets(input).collect::<Vec<_>>();
+
+        assert_eq!(
+            lines.iter().map(|(_, line)| *line).collect::<Vec<_>>(),
+            input.lines().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            lines.iter().map(|(offset, _)| *offset).collect::<Vec<_>>(),
+            [0, 9, 19, 20]
+        );
+    }
+
+    #[test]
+    fn render_points_at_text() {
+        let span = Span::new(Some(Path::new("app.desktop")), 3, 20, "Name=Foo\\q", 8..10);
+
+        assert_eq!(span.to_string(), "app.desktop:3:9");
+        assert_eq!(span.bytes, 28..30);
+        assert_eq!(
+            span.render(&"invalid value"),
+            "error: invalid value\n  |\n3 | Name=Foo\\q\n  |         ^^\n"
+        );
+    }
+}
@@ -27,3 +27,129 @@ fn translatable_inverted_key_at_the_end() {
 
     assert!(de.keywords(&[] as &[&str]).is_some());
 }
+
+#[test]
+fn find_steam_and_wine_apps() {
+    use crate::find_app_by_id;
+    use unicase::Ascii;
+
+    let entry = |path: &str, exec: &str| {
+        let input = format!("[Desktop Entry]\nName=Game\nExec={exec}\n");
+        DesktopEntry::from_str(path, &input, None::<&[&str]>).unwrap()
+    };
+
+    let entries = [
+        entry("/a/dota2.desktop", "steam steam://rungameid/570"),
+        entry("/a/portal.desktop", "steam steam://run/400//"),
+        entry(
+            "/a/Notepad++.desktop",
+            r#"env WINEPREFIX="/home/user/.wine" wine C:\\\\Program\\ Files\\\\Notepad++\\\\notepad++.exe"#,
+        ),
+        entry(
+            "/a/Foo.desktop",
+            r"env WINEPREFIX=/home/user/.wine wine C:\\\\windows\\\\command\\\\start.exe /Unix /home/user/.wine/dosdevices/c:/users/Public/Start\\ Menu/Foo\\ Bar.lnk",
+        ),
+    ];
+
+    assert_eq!(entries[0].steam_app_id(), Some("570"));
+    assert_eq!(entries[1].steam_app_id(), Some("400"));
+    assert_eq!(entries[2].wine_program().as_deref(), Some("notepad++"));
+    assert_eq!(entries[3].wine_program().as_deref(), Some("Foo Bar"));
+    assert_eq!(entries[0].wine_program(), None);
+
+    // Only the launcher is checked, not any argument which starts with `wine`.
+    for exec in [
+        "winetricks --gui setup.exe",
+        "winecfg /home/user/.wine/drive_c/setup.exe",
+        "lutris lutris:rungame/wine-foo.exe",
+        "env WINEPREFIX=/home/user/.wine winecfg foo.exe",
+    ] {
+        assert_eq!(
+            entry("/a/tool.desktop", exec).wine_program(),
+            None,
+            "{exec}"
+        );
+    }
+    assert_eq!(
+        entry(
+            "/a/Bar.desktop",
+            "env WINEDEBUG=-all /usr/bin/wine64 Bar.exe"
+        )
+        .wine_program(),
+        Some("Bar".into())
+    );
+
+    let find = |id: &str| find_app_by_id(&entries, Ascii::new(id)).map(|entry| entry.id());
+    assert_eq!(find("steam_app_400"), Some("portal"));
+    assert_eq!(find("steam_app_57"), None);
+    assert_eq!(find("Notepad++.EXE"), Some("Notepad++"));
+    assert_eq!(find("foo bar.exe"), Some("Foo"));
+}
+
+#[test]
+fn find_renamed_flatpaks_and_snap_instances() {
+    use crate::{find_app_by_id, AppIndex, AppMatcher};
+    use unicase::Ascii;
+
+    let entry = |path: &str, keys: &str| {
+        let input = format!("[Desktop Entry]\nName=App\nExec=app\n{keys}");
+        DesktopEntry::from_str(path, &input, None::<&[&str]>).unwrap()
+    };
+
+    let entries = [
+        entry(
+            "/a/org.gnome.Calls.desktop",
+            "X-Flatpak=org.gnome.Calls\nX-Flatpak-RenamedFrom=sm.puri.Calls.desktop;org.gnome.OldCalls;\n",
+        ),
+        entry(
+            "/a/com.example.Tool.desktop",
+            "X-Flatpak=com.example.ToolDev\n",
+        ),
+        entry(
+            "/a/firefox+esr_firefox.desktop",
+            "X-SnapInstanceName=firefox_esr\nX-SnapAppName=firefox\n",
+        ),
+        entry(
+            "/a/firefox_firefox.desktop",
+            "X-SnapInstanceName=firefox\nX-SnapAppName=firefox\n",
+        ),
+        // Every entry of a Flatpak shares its ID, which must not hide the main entry.
+        entry(
+            "/a/org.libreoffice.LibreOffice.calc.desktop",
+            "X-Flatpak=org.libreoffice.LibreOffice\n",
+        ),
+        entry(
+            "/a/org.libreoffice.LibreOffice.desktop",
+            "X-Flatpak=org.libreoffice.LibreOffice\n",
+        ),
+    ];
+
+    assert_eq!(
+        entries[0].flatpak_renamed_from(),
+        Some(vec!["sm.puri.Calls", "org.gnome.OldCalls"])
+    );
+    assert_eq!(entries[2].snap_instance_name(), Some("firefox_esr"));
+
+    let index = AppIndex::from_entries(&entries);
+    for (query, id) in [
+        ("sm.puri.Calls", Some("org.gnome.Calls")),
+        ("org.gnome.oldcalls", Some("org.gnome.Calls")),
+        ("com.example.ToolDev", Some("com.example.Tool")),
+        ("firefox_esr", Some("firefox+esr_firefox")),
+        ("firefox", Some("firefox_firefox")),
+        ("firefox_beta", None),
+        (
+            "org.libreoffice.LibreOffice",
+            Some("org.libreoffice.LibreOffice"),
+        ),
+    ] {
+        let found = find_app_by_id(&entries, Ascii::new(query)).map(|entry| entry.id());
+        assert_eq!(found, id, "{query}");
+        assert_eq!(index.find(query).map(|entry| entry.id()), id, "{query}");
+    }
+
+    let best = AppMatcher::new()
+        .find(&entries, "org.libreoffice.LibreOffice")
+        .unwrap();
+    assert_eq!(best.entry.id(), "org.libreoffice.LibreOffice");
+}
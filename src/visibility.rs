@@ -0,0 +1,245 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{env, ffi::OsString};
+
+use thiserror::Error;
+
+use crate::{current_desktop, find_executable, DesktopEntry};
+
+/// Keys listing the form factors an application was designed for.
+const FORM_FACTOR_KEYS: [&str; 2] = ["X-Purism-FormFactor", "X-KDE-FormFactors"];
+
+/// The environment which [`DesktopEntry::should_show`] decides for.
+#[derive(Debug, Clone, Default)]
+pub struct ShowContext {
+    desktops: Vec<String>,
+    path: Option<OsString>,
+    form_factors: Vec<String>,
+}
+
+/// Why [`DesktopEntry::should_show`] hides an entry.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HiddenReason {
+    #[error("the entry is deleted with Hidden=true")]
+    Hidden,
+    #[error("the entry sets NoDisplay=true")]
+    NoDisplay,
+    #[error("no current desktop is listed in OnlyShowIn")]
+    OnlyShowIn,
+    #[error("{0} is listed in NotShowIn")]
+    NotShowIn(String),
+    #[error("TryExec program {0} was not found or is not executable")]
+    TryExec(String),
+    #[error("no current form factor is listed in {0}")]
+    FormFactor(&'static str),
+}
+
+impl ShowContext {
+    /// A context without desktops or form factors, which does not check `TryExec`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Desktops from `XDG_CURRENT_DESKTOP` and programs from `PATH`.
+    #[inline]
+    pub fn from_env() -> Self {
+        Self {
+            desktops: current_desktop().unwrap_or_default(),
+            path: env::var_os("PATH"),
+            form_factors: Vec::new(),
+        }
+    }
+
+    /// Names of the current desktops, in order of priority. Names are case-sensitive.
+    #[inline]
+    pub fn desktops<I, S>(mut self, desktops: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.desktops = desktops.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Search path used to resolve `TryExec`, in the format of the `PATH` variable.
+    #[inline]
+    pub fn path(mut self, path: impl Into<OsString>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Form factors of the device, such as `Workstation` or `Mobile`. Entries which list
+    /// form factors are hidden unless they list one of these. Not checked by default.
+    #[inline]
+    pub fn form_factors<I, S>(mut self, form_factors: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.form_factors = form_factors.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+impl DesktopEntry {
+    /// Whether the entry should be shown in menus, following the rules of `Hidden`,
+    /// `NoDisplay`, `OnlyShowIn`, `NotShowIn` and `TryExec`.
+    pub fn should_show(&self, context: &ShowContext) -> Result<(), HiddenReason> {
+        if self.hidden() {
+            return Err(HiddenReason::Hidden);
+        }
+
+        if self.no_display() {
+            return Err(HiddenReason::NoDisplay);
+        }
+
+        self.show_in(&context.desktops)?;
+
+        if let (Some(program), Some(path)) = (self.try_exec(), context.path.as_ref()) {
+            if find_executable(program, path).is_err() {
+                return Err(HiddenReason::TryExec(program.to_owned()));
+            }
+        }
+
+        if !context.form_factors.is_empty() {
+            for key in FORM_FACTOR_KEYS {
+                let Some(value) = self.desktop_entry(key) else {
+                    continue;
+                };
+
+                let supported = list(value).any(|form_factor| {
+                    context
+                        .form_factors
+                        .iter()
+                        .any(|f| f.eq_ignore_ascii_case(form_factor))
+                });
+
+                if !supported {
+                    return Err(HiddenReason::FormFactor(key));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The first current desktop listed in either `OnlyShowIn` or `NotShowIn` decides.
+    fn show_in(&self, desktops: &[String]) -> Result<(), HiddenReason> {
+        let only_show_in = self.only_show_in();
+        let not_show_in = self.not_show_in();
+
+        for desktop in desktops {
+            if only_show_in
+                .as_ref()
+                .is_some_and(|only| only.contains(&desktop.as_str()))
+            {
+                return Ok(());
+            }
+
+            if not_show_in
+                .as_ref()
+                .is_some_and(|not| not.contains(&desktop.as_str()))
+            {
+                return Err(HiddenReason::NotShowIn(desktop.clone()));
+            }
+        }
+
+        match only_show_in {
+            Some(_) => Err(HiddenReason::OnlyShowIn),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Items of a `;` separated list, ignoring the trailing separator.
+#[inline]
+fn list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::{HiddenReason, ShowContext};
+    use crate::DesktopEntry;
+
+    fn entry(keys: &str) -> DesktopEntry {
+        let input = format!("[Desktop Entry]\nType=Application\nName=App\n{keys}");
+        DesktopEntry::from_str("app.desktop", &input, None::<&[&str]>).unwrap()
+    }
+
+    #[test]
+    fn show_in_desktops() {
+        let gnome = ShowContext::new().desktops(["ubuntu", "GNOME"]);
+
+        assert_eq!(entry("").should_show(&gnome), Ok(()));
+        assert_eq!(entry("OnlyShowIn=KDE;GNOME;").should_show(&gnome), Ok(()));
+        assert_eq!(
+            entry("OnlyShowIn=KDE;").should_show(&gnome),
+            Err(HiddenReason::OnlyShowIn)
+        );
+        assert_eq!(
+            entry("OnlyShowIn=gnome;").should_show(&gnome),
+            Err(HiddenReason::OnlyShowIn)
+        );
+        assert_eq!(
+            entry("NotShowIn=GNOME;").should_show(&gnome),
+            Err(HiddenReason::NotShowIn("GNOME".into()))
+        );
+        // Only `;` separates desktops
+        assert_eq!(
+            entry("OnlyShowIn=KDE,GNOME;").should_show(&gnome),
+            Err(HiddenReason::OnlyShowIn)
+        );
+        // The first matching desktop decides
+        assert_eq!(
+            entry("OnlyShowIn=ubuntu;\nNotShowIn=GNOME;").should_show(&gnome),
+            Ok(())
+        );
+        assert_eq!(
+            entry("NoDisplay=true").should_show(&gnome),
+            Err(HiddenReason::NoDisplay)
+        );
+        assert_eq!(
+            entry("Hidden=true").should_show(&gnome),
+            Err(HiddenReason::Hidden)
+        );
+    }
+
+    #[test]
+    fn show_with_try_exec_and_form_factor() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join("app"), "").unwrap();
+        fs::set_permissions(temp.path().join("app"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let context = ShowContext::new()
+            .path(temp.path())
+            .form_factors(["Mobile"]);
+
+        assert_eq!(entry("TryExec=app").should_show(&context), Ok(()));
+        assert_eq!(
+            entry("TryExec=missing").should_show(&context),
+            Err(HiddenReason::TryExec("missing".into()))
+        );
+        assert_eq!(
+            entry("X-Purism-FormFactor=Workstation;Mobile;").should_show(&context),
+            Ok(())
+        );
+        assert_eq!(
+            entry("X-KDE-FormFactors=desktop").should_show(&context),
+            Err(HiddenReason::FormFactor("X-KDE-FormFactors"))
+        );
+
+        // Without a path, TryExec is not checked
+        assert_eq!(
+            entry("TryExec=missing").should_show(&ShowContext::new()),
+            Ok(())
+        );
+    }
+}
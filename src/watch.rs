@@ -0,0 +1,478 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+
+use crate::{DesktopEntry, DirLayout, FileKind, FileSystem, Iter, RealFs};
+
+/// A change to the desktop entries visible through a [`Watcher`].
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    /// A desktop file ID appeared.
+    Added { id: String, entry: DesktopEntry },
+    /// The entry for a desktop file ID was modified, or is now provided by another file, such
+    /// as a user override shadowing a system entry.
+    Changed { id: String, entry: DesktopEntry },
+    /// A desktop file ID is no longer provided by any directory.
+    Removed { id: String },
+}
+
+/// Watches application directories with inotify, reporting debounced changes to the entries
+/// they provide.
+///
+/// Directories are given in order of priority. When several contain the same desktop file
+/// ID, only the first one is visible. Directories which do not exist yet are picked up once
+/// they are created. A file which fails to decode keeps providing its last valid entry.
+pub struct Watcher {
+    inotify: Inotify,
+    fs: Arc<dyn FileSystem>,
+    roots: Vec<PathBuf>,
+    locales: Vec<String>,
+    debounce: Duration,
+    /// When the last file system event was read, if changes have not been reported since.
+    pending: Option<Instant>,
+    watches: HashMap<PathBuf, WatchDescriptor>,
+    visible: BTreeMap<String, Visible>,
+}
+
+struct Visible {
+    entry: DesktopEntry,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+const MASK: WatchMask = WatchMask::CREATE
+    .union(WatchMask::DELETE)
+    .union(WatchMask::CLOSE_WRITE)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::ATTRIB)
+    .union(WatchMask::DELETE_SELF)
+    .union(WatchMask::MOVE_SELF)
+    .union(WatchMask::ONLYDIR);
+
+impl Watcher {
+    /// Watch the given directories, such as [`crate::default_paths`], and load their entries.
+    #[inline]
+    pub fn new<I, L>(directories: I, locales: &[L]) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = PathBuf>,
+        L: AsRef<str>,
+    {
+        Self::new_with(Arc::new(RealFs), directories, locales)
+    }
+
+    /// Like [`Watcher::new`], reading entries from the given file system.
+    ///
+    /// Changes are noticed with inotify, which only sees the file system of the host. The
+    /// entries are read from `fs` whenever the watched directories change.
+    pub fn new_with<I, L>(
+        fs: Arc<dyn FileSystem>,
+        directories: I,
+        locales: &[L],
+    ) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = PathBuf>,
+        L: AsRef<str>,
+    {
+        let mut watcher = Self {
+            inotify: Inotify::init()?,
+            fs,
+            roots: directories.into_iter().collect(),
+            locales: locales.iter().map(|l| l.as_ref().to_owned()).collect(),
+            debounce: Duration::from_millis(200),
+            pending: None,
+            watches: HashMap::new(),
+            visible: BTreeMap::new(),
+        };
+
+        watcher.rescan();
+        Ok(watcher)
+    }
+
+    /// Watch the [`DirLayout::application_dirs`] of `layout`, and load their entries.
+    #[inline]
+    pub fn from_layout<L: AsRef<str>>(layout: &DirLayout, locales: &[L]) -> io::Result<Self> {
+        Self::new(layout.application_dirs(), locales)
+    }
+
+    /// Like [`Watcher::from_layout`], reading entries from the given file system.
+    #[inline]
+    pub fn from_layout_with<L: AsRef<str>>(
+        fs: Arc<dyn FileSystem>,
+        layout: &DirLayout,
+        locales: &[L],
+    ) -> io::Result<Self> {
+        Self::new_with(fs, layout.application_dirs(), locales)
+    }
+
+    /// How long the directories must be quiet before changes are reported. Defaults to 200ms.
+    #[inline]
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Entries currently visible, sorted by desktop file ID.
+    #[inline]
+    pub fn entries(&self) -> impl Iterator<Item = &DesktopEntry> {
+        self.visible.values().map(|visible| &visible.entry)
+    }
+
+    /// Block until the visible entries change, and return the changes.
+    pub fn wait(&mut self) -> io::Result<Vec<WatchEvent>> {
+        let mut buffer = [0; 4096];
+
+        loop {
+            self.inotify.read_events_blocking(&mut buffer)?;
+            self.settle(&mut buffer)?;
+            self.pending = None;
+
+            let events = self.rescan();
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Read pending file system events without blocking, and return the changes to the
+    /// visible entries once no events arrived for the debounce duration.
+    ///
+    /// Intended to be called whenever the file descriptor of the watcher is readable, and
+    /// again at the [`Watcher::deadline`].
+    pub fn poll(&mut self) -> io::Result<Vec<WatchEvent>> {
+        let mut buffer = [0; 4096];
+
+        if self.drain(&mut buffer)? {
+            self.pending = Some(Instant::now());
+        }
+
+        match self.pending {
+            Some(last) if last.elapsed() >= self.debounce => {
+                self.pending = None;
+                Ok(self.rescan())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// When [`Watcher::poll`] should be called to report changes which are waiting for the
+    /// debounce duration to pass, if any.
+    #[inline]
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.map(|last| last + self.debounce)
+    }
+
+    /// Drain events until none arrived for the debounce duration.
+    fn settle(&mut self, buffer: &mut [u8]) -> io::Result<()> {
+        loop {
+            thread::sleep(self.debounce);
+
+            if !self.drain(buffer)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Read every pending event without blocking, returning whether there were any.
+    fn drain(&mut self, buffer: &mut [u8]) -> io::Result<bool> {
+        let mut drained = false;
+
+        loop {
+            match self.inotify.read_events(buffer) {
+                Ok(_) => drained = true,
+                Err(why) if why.kind() == io::ErrorKind::WouldBlock => return Ok(drained),
+                Err(why) => return Err(why),
+            }
+        }
+    }
+
+    /// Walk every directory again, compare the visible entries, and update the watches.
+    fn rescan(&mut self) -> Vec<WatchEvent> {
+        let walked = Arc::new(Mutex::new(Vec::new()));
+
+        let paths = Iter::builder(self.roots.clone())
+            .filesystem(self.fs.clone())
+            .on_directory({
+                let walked = walked.clone();
+                move |dir, _| {
+                    walked.lock().unwrap().push(dir.to_path_buf());
+                    true
+                }
+            })
+            .build()
+            .with_ids();
+
+        let mut previous = std::mem::take(&mut self.visible);
+        let mut events = Vec::new();
+
+        for (id, path) in paths {
+            let id = String::from(id);
+
+            // Shadowed by a directory of higher priority
+            if self.visible.contains_key(&id) {
+                continue;
+            }
+
+            let Ok(metadata) = self.fs.metadata(&path) else {
+                continue;
+            };
+            let size = metadata.len;
+            let modified = metadata.modified;
+
+            let visible = match previous.remove(&id) {
+                Some(visible)
+                    if visible.entry.path == path
+                        && visible.size == size
+                        && visible.modified == modified =>
+                {
+                    visible
+                }
+                before => {
+                    let Ok(mut entry) =
+                        DesktopEntry::from_path_with(&*self.fs, path, Some(&self.locales))
+                    else {
+                        // Keep the last good entry until the file is fixed or removed.
+                        if let Some(before) = before {
+                            self.visible.insert(id, before);
+                        }
+                        continue;
+                    };
+                    entry.appid.clone_from(&id);
+
+                    let id = id.clone();
+                    events.push(match before {
+                        Some(_) => WatchEvent::Changed {
+                            id,
+                            entry: entry.clone(),
+                        },
+                        None => WatchEvent::Added {
+                            id,
+                            entry: entry.clone(),
+                        },
+                    });
+
+                    Visible {
+                        entry,
+                        size,
+                        modified,
+                    }
+                }
+            };
+
+            self.visible.insert(id, visible);
+        }
+
+        events.extend(previous.into_keys().map(|id| WatchEvent::Removed { id }));
+
+        let is_dir = |dir: &Path| {
+            self.fs
+                .metadata(dir)
+                .is_ok_and(|metadata| metadata.kind == FileKind::Dir)
+        };
+
+        let mut directories = std::mem::take(&mut *walked.lock().unwrap());
+        // Watch the closest existing ancestor of missing directories to notice their creation.
+        directories.extend(
+            self.roots
+                .iter()
+                .filter(|root| !is_dir(root))
+                .filter_map(|root| {
+                    root.ancestors()
+                        .skip(1)
+                        .find(|dir| is_dir(dir))
+                        .map(Path::to_path_buf)
+                }),
+        );
+        self.sync_watches(directories);
+
+        events
+    }
+
+    fn sync_watches(&mut self, directories: Vec<PathBuf>) {
+        let mut watches = HashMap::with_capacity(directories.len());
+
+        for dir in directories {
+            if watches.contains_key(&dir) {
+                continue;
+            }
+
+            let wd = match self.watches.remove(&dir) {
+                Some(wd) => wd,
+                None => match self.inotify.watches().add(&dir, MASK) {
+                    Ok(wd) => wd,
+                    Err(why) => {
+                        log::warn!("failed to watch {}: {why}", dir.display());
+                        continue;
+                    }
+                },
+            };
+
+            watches.insert(dir, wd);
+        }
+
+        // Watches of deleted directories were already removed by the kernel.
+        for (_, wd) in std::mem::replace(&mut self.watches, watches) {
+            let _ = self.inotify.watches().remove(wd);
+        }
+    }
+}
+
+impl AsRawFd for Watcher {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.inotify.as_raw_fd()
+    }
+}
+
+impl AsFd for Watcher {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use super::{WatchEvent, Watcher};
+    use crate::MemoryFs;
+
+    fn summarize(events: Vec<WatchEvent>) -> Vec<(&'static str, String, Option<String>)> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                WatchEvent::Added { id, entry } => {
+                    ("added", id, entry.name(&[] as &[&str]).map(|n| n.into()))
+                }
+                WatchEvent::Changed { id, entry } => {
+                    ("changed", id, entry.name(&[] as &[&str]).map(|n| n.into()))
+                }
+                WatchEvent::Removed { id } => ("removed", id, None),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn watcher_reports_shadowed_changes() {
+        let temp = tempfile::tempdir().unwrap();
+        let user = temp.path().join("user/applications");
+        let system = temp.path().join("system/applications");
+        fs::create_dir_all(&system).unwrap();
+
+        let mut watcher = Watcher::new([user.clone(), system.clone()], &[] as &[&str])
+            .unwrap()
+            .debounce(Duration::from_millis(20));
+        assert_eq!(watcher.entries().count(), 0);
+
+        fs::write(system.join("app.desktop"), "[Desktop Entry]\nName=System\n").unwrap();
+        assert_eq!(
+            summarize(watcher.wait().unwrap()),
+            [("added", "app".into(), Some("System".into()))]
+        );
+
+        // The user directory does not exist yet.
+        fs::create_dir_all(&user).unwrap();
+        fs::write(user.join("app.desktop"), "[Desktop Entry]\nName=User\n").unwrap();
+        assert_eq!(
+            summarize(watcher.wait().unwrap()),
+            [("changed", "app".into(), Some("User".into()))]
+        );
+
+        fs::remove_file(user.join("app.desktop")).unwrap();
+        assert_eq!(
+            summarize(watcher.wait().unwrap()),
+            [("changed", "app".into(), Some("System".into()))]
+        );
+
+        fs::remove_file(system.join("app.desktop")).unwrap();
+        assert_eq!(
+            summarize(watcher.wait().unwrap()),
+            [("removed", "app".into(), None)]
+        );
+        assert!(watcher.poll().unwrap().is_empty());
+        assert!(watcher.deadline().is_none());
+
+        // Polling reports nothing until the debounce duration has passed.
+        fs::write(system.join("app.desktop"), "[Desktop Entry]\nName=System\n").unwrap();
+        while watcher.poll().unwrap().is_empty() && watcher.deadline().is_none() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let deadline = watcher.deadline().unwrap();
+        std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        assert_eq!(
+            summarize(watcher.poll().unwrap()),
+            [("added", "app".into(), Some("System".into()))]
+        );
+        assert!(watcher.deadline().is_none());
+    }
+
+    #[test]
+    fn watcher_reads_entries_from_filesystem() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.add_file("/apps/kde/app.desktop", "[Desktop Entry]\nName=App\n");
+
+        let watcher = Watcher::new_with(fs, [PathBuf::from("/apps")], &[] as &[&str]).unwrap();
+        let entries = watcher.entries().collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].appid, "kde-app");
+        assert_eq!(entries[0].name(&[] as &[&str]).unwrap(), "App");
+    }
+
+    #[test]
+    fn watcher_keeps_entries_which_become_invalid() {
+        let temp = tempfile::tempdir().unwrap();
+        let apps = temp.path().join("applications");
+        fs::create_dir_all(&apps).unwrap();
+        fs::write(apps.join("app.desktop"), "[Desktop Entry]\nName=App\n").unwrap();
+
+        let mut watcher = Watcher::new([apps.clone()], &[] as &[&str])
+            .unwrap()
+            .debounce(Duration::from_millis(20));
+
+        // Overwriting the entry with an invalid file neither changes nor removes it.
+        fs::write(apps.join("app.desktop"), "Name=Broken\n").unwrap();
+        fs::write(apps.join("other.desktop"), "[Desktop Entry]\nName=Other\n").unwrap();
+        assert_eq!(
+            summarize(watcher.wait().unwrap()),
+            [("added", "other".into(), Some("Other".into()))]
+        );
+        assert_eq!(
+            watcher
+                .entries()
+                .map(|entry| entry.name(&[] as &[&str]).unwrap().into_owned())
+                .collect::<Vec<_>>(),
+            ["App", "Other"]
+        );
+
+        fs::write(apps.join("app.desktop"), "[Desktop Entry]\nName=Fixed\n").unwrap();
+        assert_eq!(
+            summarize(watcher.wait().unwrap()),
+            [("changed", "app".into(), Some("Fixed".into()))]
+        );
+
+        fs::write(apps.join("app.desktop"), "Name=Broken\n").unwrap();
+        fs::remove_file(apps.join("app.desktop")).unwrap();
+        assert_eq!(
+            summarize(watcher.wait().unwrap()),
+            [("removed", "app".into(), None)]
+        );
+    }
+}
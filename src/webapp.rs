@@ -0,0 +1,364 @@
+// Copyright 2021 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+};
+
+use crate::DesktopEntry;
+
+/// Chromium-based browsers which install web apps, with the prefix of their window app IDs,
+/// their programs and their Flatpak IDs.
+const BROWSERS: [(Browser, &str, &[&str], &str); 5] = [
+    (
+        Browser::Chrome,
+        "chrome",
+        &["google-chrome", "google-chrome-stable", "chrome"],
+        "com.google.Chrome",
+    ),
+    (
+        Browser::Chromium,
+        "chromium",
+        &["chromium", "chromium-browser"],
+        "org.chromium.Chromium",
+    ),
+    (
+        Browser::Brave,
+        "brave",
+        &["brave", "brave-browser", "brave-browser-stable"],
+        "com.brave.Browser",
+    ),
+    (
+        Browser::Edge,
+        "msedge",
+        &["microsoft-edge", "microsoft-edge-stable", "msedge"],
+        "com.microsoft.Edge",
+    ),
+    (
+        Browser::Vivaldi,
+        "vivaldi",
+        &["vivaldi", "vivaldi-stable"],
+        "com.vivaldi.Vivaldi",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Browser {
+    Chrome,
+    Chromium,
+    Brave,
+    Edge,
+    Vivaldi,
+}
+
+/// A web app installed by a Chromium-based browser, identified by its 32 character app ID.
+///
+/// Its windows have the app ID `<browser>-<app id>-<profile>` on Wayland, and the class
+/// `crx_<app id>` on X11.
+///
+/// ```
+/// use freedesktop_desktop_entry::{Browser, WebApp};
+///
+/// let app = WebApp::from_window_id("brave-cinhimbnkkaeohfgghhklpknlkffjgod-Default").unwrap();
+/// assert_eq!(app.browser, Some(Browser::Brave));
+/// assert_eq!(app.app_id, "cinhimbnkkaeohfgghhklpknlkffjgod");
+/// assert_eq!(app.profile.as_deref(), Some("Default"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WebApp {
+    pub browser: Option<Browser>,
+    pub app_id: String,
+    /// The profile directory, with spaces replaced by `_` as in window app IDs.
+    pub profile: Option<String>,
+}
+
+impl WebApp {
+    /// Parse the app ID of a window, either `<browser>-<app id>-<profile>` or `crx_<app id>`.
+    pub fn from_window_id(window_id: &str) -> Option<Self> {
+        if let Some(app_id) = window_id.strip_prefix("crx_") {
+            return is_app_id(app_id).then(|| WebApp {
+                browser: None,
+                app_id: app_id.to_owned(),
+                profile: None,
+            });
+        }
+
+        let (prefix, rest) = window_id.split_once('-')?;
+        let (app_id, profile) = rest.split_once('-')?;
+
+        if !is_app_id(app_id) || profile.is_empty() {
+            return None;
+        }
+
+        Some(WebApp {
+            browser: Browser::from_prefix(prefix),
+            app_id: app_id.to_owned(),
+            profile: Some(profile.to_owned()),
+        })
+    }
+
+    /// The web app which an entry launches, from the `--app-id` and `--profile-directory`
+    /// arguments of its `Exec`, its file name, and its `StartupWMClass`.
+    pub fn from_entry(entry: &DesktopEntry) -> Option<Self> {
+        let args = split_args(entry.exec().unwrap_or_default());
+
+        let arg = |name: &str| {
+            args.iter()
+                .find_map(|arg| arg.strip_prefix(name)?.strip_prefix('='))
+        };
+
+        // Flatpak exports are named `<flatpak id>.flextop.<window id>`.
+        let from_file = entry
+            .path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| WebApp::from_window_id(stem.rsplit('.').next()?));
+        let from_class = entry.startup_wm_class().and_then(WebApp::from_window_id);
+
+        let app_id = arg("--app-id")
+            .filter(|app_id| is_app_id(app_id))
+            .map(str::to_owned)
+            .or_else(|| from_file.as_ref().map(|app| app.app_id.clone()))
+            .or_else(|| from_class.as_ref().map(|app| app.app_id.clone()))?;
+
+        let profile = arg("--profile-directory")
+            .map(|profile| profile.replace(' ', "_"))
+            .or_else(|| from_file.as_ref().and_then(|app| app.profile.clone()));
+
+        let browser = Browser::from_args(entry, &args).or_else(|| from_file?.browser);
+
+        Some(WebApp {
+            browser,
+            app_id,
+            profile,
+        })
+    }
+
+    /// Whether both are the same web app. Browsers and profiles are only compared if both are
+    /// known.
+    pub fn matches(&self, other: &WebApp) -> bool {
+        let browsers = match (self.browser, other.browser) {
+            (Some(a), Some(b)) => a.same_windows(b),
+            _ => true,
+        };
+
+        let profiles = match (&self.profile, &other.profile) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+
+        self.app_id == other.app_id && browsers && profiles
+    }
+}
+
+impl DesktopEntry {
+    #[inline]
+    pub fn web_app(&self) -> Option<WebApp> {
+        WebApp::from_entry(self)
+    }
+
+    /// Whether this entry launches the web app of a window with the app ID `window_id`.
+    #[inline]
+    pub fn matches_web_app(&self, window_id: &str) -> bool {
+        WebApp::from_window_id(window_id)
+            .is_some_and(|window| self.web_app().is_some_and(|app| window.matches(&app)))
+    }
+}
+
+/// Find the entry of a web app window, by its app ID or class.
+#[inline]
+pub fn find_web_app<'a>(entries: &'a [DesktopEntry], window_id: &str) -> Option<&'a DesktopEntry> {
+    let window = WebApp::from_window_id(window_id)?;
+
+    entries
+        .iter()
+        .find(|entry| entry.web_app().is_some_and(|app| window.matches(&app)))
+}
+
+impl Browser {
+    #[inline]
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        BROWSERS
+            .iter()
+            .find(|(_, window_prefix, ..)| *window_prefix == prefix)
+            .map(|(browser, ..)| *browser)
+    }
+
+    /// The browser which the program or Flatpak of an entry runs.
+    fn from_args(entry: &DesktopEntry, args: &[String]) -> Option<Self> {
+        let program = args
+            .first()
+            .and_then(|program| Path::new(program).file_name()?.to_str())?;
+
+        let flatpak = entry
+            .flatpak()
+            .or_else(|| entry.desktop_entry("X-Flatpak-Part-Of"))
+            .or_else(|| {
+                (program == "flatpak").then(|| {
+                    args.iter()
+                        .skip(1)
+                        .find(|arg| !arg.starts_with('-') && *arg != "run")
+                        .map(String::as_str)
+                })?
+            });
+
+        BROWSERS
+            .iter()
+            .find(|(_, _, programs, flatpak_id)| {
+                programs.contains(&program) || flatpak == Some(*flatpak_id)
+            })
+            .map(|(browser, ..)| *browser)
+    }
+
+    /// Chromium names the windows of its web apps like Chrome does.
+    #[inline]
+    fn same_windows(self, other: Browser) -> bool {
+        let family = |browser| match browser {
+            Browser::Chromium => Browser::Chrome,
+            browser => browser,
+        };
+
+        family(self) == family(other)
+    }
+}
+
+/// Arguments of a command, which may be quoted with `"` or `'` as browsers write them, unlike
+/// [`DesktopEntry::parse_exec`].
+fn split_args(exec: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut quote = None;
+    let mut in_arg = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_ascii_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => arg.extend(chars.next()),
+            (_, c) => {
+                arg.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(arg);
+    }
+
+    args
+}
+
+/// Chromium app IDs are 32 characters from `a` to `p`.
+#[inline]
+fn is_app_id(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|b| (b'a'..=b'p').contains(&b))
+}
+
+impl Display for Browser {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Browser::Chrome => "Google Chrome",
+            Browser::Chromium => "Chromium",
+            Browser::Brave => "Brave",
+            Browser::Edge => "Microsoft Edge",
+            Browser::Vivaldi => "Vivaldi",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_web_app, Browser, WebApp};
+    use crate::{find_app_by_id, AppIndex, DesktopEntry};
+
+    const APP_ID: &str = "cinhimbnkkaeohfgghhklpknlkffjgod";
+
+    #[test]
+    fn web_app_of_entries() {
+        let brave = DesktopEntry::from_path(
+            "tests_entries/com.brave.Browser.flextop.brave-cinhimbnkkaeohfgghhklpknlkffjgod-Default.desktop",
+            None::<&[&str]>,
+        )
+        .unwrap();
+
+        assert_eq!(
+            brave.web_app(),
+            Some(WebApp {
+                browser: Some(Browser::Brave),
+                app_id: APP_ID.into(),
+                profile: Some("Default".into()),
+            })
+        );
+
+        let chrome = DesktopEntry::from_str(
+            format!("/home/user/.local/share/applications/chrome-{APP_ID}-Profile_1.desktop"),
+            &format!(
+                "[Desktop Entry]\nName=YouTube Music\nExec=/opt/google/chrome/google-chrome \"--profile-directory=Profile 1\" --app-id={APP_ID}\n"
+            ),
+            None::<&[&str]>,
+        )
+        .unwrap();
+
+        assert_eq!(
+            chrome.web_app(),
+            Some(WebApp {
+                browser: Some(Browser::Chrome),
+                app_id: APP_ID.into(),
+                profile: Some("Profile_1".into()),
+            })
+        );
+
+        let entries = [chrome, brave];
+        let found = |window_id: &str| {
+            find_web_app(&entries, window_id).map(|entry| entry.web_app().unwrap().browser)
+        };
+
+        assert_eq!(found(&format!("crx_{APP_ID}")), Some(Some(Browser::Chrome)));
+        assert_eq!(
+            found(&format!("brave-{APP_ID}-Default")),
+            Some(Some(Browser::Brave))
+        );
+        assert_eq!(
+            found(&format!("chrome-{APP_ID}-Profile_1")),
+            Some(Some(Browser::Chrome))
+        );
+        assert_eq!(found(&format!("msedge-{APP_ID}-Default")), None);
+        assert_eq!(found(&format!("chrome-{APP_ID}-Default")), None);
+        assert_eq!(found("org.gnome.Maps"), None);
+
+        assert!(entries[1].matches_web_app(&format!("brave-{APP_ID}-Default")));
+
+        // Web app windows take precedence over the rest of the app ID cascade.
+        let index = AppIndex::from_entries(&entries);
+        for (window_id, browser) in [
+            (format!("brave-{APP_ID}-Default"), Some(Browser::Brave)),
+            (format!("crx_{APP_ID}"), Some(Browser::Chrome)),
+            (format!("chrome-{APP_ID}-Profile_1"), Some(Browser::Chrome)),
+            (format!("chrome-{APP_ID}-Default"), None),
+        ] {
+            let found = find_app_by_id(&entries, unicase::Ascii::new(&window_id));
+            assert_eq!(
+                found.map(|entry| entry.web_app().unwrap().browser),
+                browser.map(Some),
+                "{window_id}"
+            );
+            assert_eq!(
+                index.find(&window_id).map(|entry| &entry.path),
+                found.map(|entry| &entry.path),
+                "{window_id}"
+            );
+        }
+    }
+}